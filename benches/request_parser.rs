@@ -0,0 +1,86 @@
+use std::io::BufRead;
+use std::sync::OnceLock;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use regex::Regex;
+use throwscape::http::HttpRequest;
+
+const MINIMAL_REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+const BROWSER_REQUEST: &[u8] = b"GET /assets/app.js?v=3 HTTP/1.1\r\n\
+Host: www.example.com\r\n\
+User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n\
+Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+Accept-Language: en-US,en;q=0.5\r\n\
+Accept-Encoding: gzip, deflate, br, zstd\r\n\
+Referer: https://www.example.com/\r\n\
+Connection: keep-alive\r\n\
+Cookie: session=8f2a6d1c9b7e4f30a5d2c1b0e9f8a7d6; theme=dark\r\n\
+If-None-Match: \"5e8f-17a2b3c4d5e\"\r\n\
+Sec-Fetch-Dest: script\r\n\
+Sec-Fetch-Mode: no-cors\r\n\
+Sec-Fetch-Site: same-origin\r\n\
+\r\n";
+
+// the previous parser: a String per line, matched against regular expressions
+struct RegexRequest {
+    _method: String,
+    _path: String,
+    _query: Option<String>,
+    _headers: Vec<(String, String)>,
+}
+
+fn parse_with_regexes(mut raw_request: &[u8]) -> Option<RegexRequest> {
+    static REQUEST_LINE_REGEXES: OnceLock<(Regex, Regex)> = OnceLock::new();
+    static HEADER_REGEX: OnceLock<Regex> = OnceLock::new();
+    let (origin_form_regex, http_version_regex) = REQUEST_LINE_REGEXES.get_or_init(|| {
+        (
+            Regex::new(r"^([^?[:space:]]*)(?:\?([^#]*))?$").unwrap(),
+            Regex::new(r"^HTTP/(\d)\.(\d)$").unwrap(),
+        )
+    });
+    let header_regex = HEADER_REGEX.get_or_init(|| Regex::new(r"^([^:[:space:]]+):(.+)$").unwrap());
+
+    let mut request_line = String::new();
+    raw_request.read_line(&mut request_line).ok()?;
+    let mut elements = request_line.split(' ');
+    let method = String::from(elements.next()?);
+    let target = origin_form_regex.captures(elements.next()?)?;
+    http_version_regex.captures(elements.next()?.trim_end())?;
+
+    let mut headers = vec![];
+    loop {
+        let mut line_buffer = String::new();
+        raw_request.read_line(&mut line_buffer).ok()?;
+        let line = line_buffer.trim_end_matches(&['\r', '\n'][..]);
+        if line.is_empty() {
+            break;
+        }
+        let captures = header_regex.captures(line)?;
+        headers.push((String::from(&captures[1]), String::from(&captures[2])));
+    }
+
+    Some(RegexRequest {
+        _method: method,
+        _path: String::from(&target[1]),
+        _query: target.get(2).map(|query| String::from(query.as_str())),
+        _headers: headers,
+    })
+}
+
+fn request_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("request_parser");
+    group.throughput(Throughput::Elements(1));
+
+    for (name, raw_request) in [("minimal", MINIMAL_REQUEST), ("browser", BROWSER_REQUEST)].iter() {
+        group.bench_with_input(BenchmarkId::new("hand_written", name), raw_request, |b, raw_request| {
+            b.iter(|| HttpRequest::from_bytes(black_box(raw_request)).unwrap().unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("regex", name), raw_request, |b, raw_request| {
+            b.iter(|| parse_with_regexes(black_box(raw_request)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, request_parser);
+criterion_main!(benches);
@@ -0,0 +1,108 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use throwscape::scheduler::{Job, ThreadPool};
+
+const THREADS: u32 = 4;
+const JOBS: u64 = 10_000;
+
+// the previous design: a dispatcher thread waits for an idle worker, then hands it the next job
+struct DispatcherPool {
+    job_sender: Option<Sender<Job>>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DispatcherPool {
+    fn new(threads: u32) -> DispatcherPool {
+        let (waiting_sender, waiting_receiver) = mpsc::channel::<u32>();
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+
+        let mut worker_senders = vec![];
+        let mut handles = vec![];
+        for id in 0..threads {
+            let waiting_sender = waiting_sender.clone();
+            let (worker_sender, worker_receiver) = mpsc::channel::<Job>();
+            worker_senders.push(worker_sender);
+            handles.push(thread::spawn(move || {
+                while waiting_sender.send(id).is_ok() {
+                    match worker_receiver.recv() {
+                        Ok(mut job) => job(),
+                        Err(_) => break,
+                    }
+                }
+            }));
+        }
+        handles.push(thread::spawn(move || dispatch(waiting_receiver, job_receiver, worker_senders)));
+
+        DispatcherPool { job_sender: Some(job_sender), threads: handles }
+    }
+
+    fn handle_job(&self, job: Job) {
+        if let Some(job_sender) = &self.job_sender {
+            let _ = job_sender.send(job);
+        }
+    }
+}
+
+fn dispatch(waiting_receiver: Receiver<u32>, job_receiver: Receiver<Job>, worker_senders: Vec<Sender<Job>>) {
+    while let Ok(id) = waiting_receiver.recv() {
+        let job = match job_receiver.recv() {
+            Ok(job) => job,
+            Err(_) => break,
+        };
+        if worker_senders[id as usize].send(job).is_err() {
+            break;
+        }
+    }
+}
+
+impl Drop for DispatcherPool {
+    fn drop(&mut self) {
+        self.job_sender.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+// stands in for the short CPU bound part of answering a request
+fn work(iterations: u64) -> u64 {
+    (0..iterations).fold(0u64, |hash, value| hash.wrapping_mul(31).wrapping_add(value))
+}
+
+fn run_jobs<F>(iterations: u64, handle_job: F)
+where
+    F: Fn(Job),
+{
+    let (finished_sender, finished_receiver) = mpsc::channel();
+    for _ in 0..JOBS {
+        let finished_sender = finished_sender.clone();
+        handle_job(Box::new(move || {
+            let _ = finished_sender.send(work(iterations));
+        }));
+    }
+    for _ in 0..JOBS {
+        let _ = finished_receiver.recv();
+    }
+}
+
+fn thread_pool_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("thread_pool");
+    group.throughput(Throughput::Elements(JOBS));
+
+    let work_stealing = ThreadPool::new(THREADS, JOBS as usize);
+    let dispatcher = DispatcherPool::new(THREADS);
+    for iterations in [0u64, 1_000, 10_000].iter() {
+        group.bench_with_input(BenchmarkId::new("work_stealing", iterations), iterations, |b, iterations| {
+            b.iter(|| run_jobs(*iterations, |job| work_stealing.handle_job(job)))
+        });
+        group.bench_with_input(BenchmarkId::new("dispatcher", iterations), iterations, |b, iterations| {
+            b.iter(|| run_jobs(*iterations, |job| dispatcher.handle_job(job)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, thread_pool_throughput);
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    // an already listening socket, ex. passed by systemd or the process throwscape replaces
+    Inherited(RawFd),
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenAddress::Tcp(address) => write!(f, "{}", address),
+            ListenAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+            ListenAddress::Inherited(fd) => write!(f, "fd:{}", fd),
+        }
+    }
+}
+
+// a single listening socket, ex. "0.0.0.0:8080", "[::]:8443", "unix:/run/throwscape.sock" or "fd:3"
+#[derive(Clone, Debug, PartialEq)]
+pub struct BindAddress {
+    pub address: ListenAddress,
+    pub is_tls: bool,
+}
+
+impl BindAddress {
+    // "http://" and "https://" pick the protocol explicitly, bare addresses use TLS whenever certificates are configured,
+    // host names bind every address they resolve to (ex. "localhost:8080" on both 127.0.0.1 and ::1)
+    pub fn parse(value: &str, has_tls_certificates: bool) -> Option<Vec<BindAddress>> {
+        // unix sockets are meant for proxies on the same machine, which already terminate TLS
+        if let Some(path) = value.strip_prefix("unix:") {
+            if path.is_empty() {
+                return None;
+            }
+            return Some(vec![BindAddress { address: ListenAddress::Unix(PathBuf::from(path)), is_tls: false }]);
+        }
+
+        let (address, is_tls) = if let Some(address) = value.strip_prefix("https://") {
+            (address, true)
+        } else if let Some(address) = value.strip_prefix("http://") {
+            (address, false)
+        } else {
+            (value, has_tls_certificates)
+        };
+
+        if let Some(fd) = address.strip_prefix("fd:") {
+            let fd = fd.parse::<RawFd>().ok().filter(|fd| *fd >= 0)?;
+            return Some(vec![BindAddress { address: ListenAddress::Inherited(fd), is_tls }]);
+        }
+
+        let addresses = match address.parse::<SocketAddr>() {
+            Ok(address) => vec![address],
+            Err(_) => address.to_socket_addrs().ok()?.collect(),
+        };
+        if addresses.is_empty() {
+            return None;
+        }
+
+        Some(
+            addresses
+                .into_iter()
+                .map(|address| BindAddress { address: ListenAddress::Tcp(address), is_tls })
+                .collect(),
+        )
+    }
+
+    // an unspecified IPv6 address also accepts IPv4 connections, unless those are bound separately on the same port
+    pub fn is_dual_stack(&self, bind_addresses: &[BindAddress]) -> bool {
+        match self.address {
+            ListenAddress::Tcp(SocketAddr::V6(address)) if address.ip().is_unspecified() => {
+                !bind_addresses.iter().any(|other| match other.address {
+                    ListenAddress::Tcp(other) => other.is_ipv4() && other.port() == address.port(),
+                    ListenAddress::Unix(_) | ListenAddress::Inherited(_) => false,
+                })
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+
+    use super::{BindAddress, ListenAddress};
+
+    fn parse(value: &str, has_tls_certificates: bool) -> Vec<BindAddress> {
+        BindAddress::parse(value, has_tls_certificates).unwrap()
+    }
+
+    #[test]
+    fn parses_ipv4_ipv6_and_protocols() {
+        assert_eq!(parse("127.0.0.1:8080", false), vec![BindAddress {
+            address: ListenAddress::Tcp(SocketAddr::from(([127, 0, 0, 1], 8080))),
+            is_tls: false,
+        }]);
+        assert_eq!(parse("[::]:8443", true)[0].address, ListenAddress::Tcp("[::]:8443".parse().unwrap()));
+        assert!(parse("[::]:8443", true)[0].is_tls);
+        assert!(!parse("http://0.0.0.0:80", true)[0].is_tls);
+        assert!(parse("https://0.0.0.0:443", false)[0].is_tls);
+
+        assert_eq!(BindAddress::parse("0.0.0.0", false), None);
+        assert_eq!(BindAddress::parse("[::]:http", false), None);
+    }
+
+    #[test]
+    fn parses_unix_sockets_without_tls() {
+        assert_eq!(parse("unix:/run/throwscape.sock", true), vec![BindAddress {
+            address: ListenAddress::Unix(PathBuf::from("/run/throwscape.sock")),
+            is_tls: false,
+        }]);
+        assert_eq!(BindAddress::parse("unix:", false), None);
+    }
+
+    #[test]
+    fn parses_inherited_descriptors() {
+        assert_eq!(parse("https://fd:3", false), vec![BindAddress {
+            address: ListenAddress::Inherited(3),
+            is_tls: true,
+        }]);
+        assert!(!parse("fd:4", false)[0].is_tls);
+        assert_eq!(BindAddress::parse("fd:-1", false), None);
+    }
+
+    #[test]
+    fn unspecified_ipv6_is_dual_stack_unless_ipv4_is_bound_as_well() {
+        let any_ipv6 = parse("[::]:8080", false).remove(0);
+        let loopback_ipv6 = parse("[::1]:8080", false).remove(0);
+        let any_ipv4 = parse("0.0.0.0:8080", false).remove(0);
+        let other_port = parse("0.0.0.0:9090", false).remove(0);
+
+        assert!(any_ipv6.is_dual_stack(&[any_ipv6.clone(), other_port]));
+        assert!(!any_ipv6.is_dual_stack(&[any_ipv6.clone(), any_ipv4]));
+        assert!(!loopback_ipv6.clone().is_dual_stack(&[loopback_ipv6]));
+    }
+}
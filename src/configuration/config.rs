@@ -1,91 +1,428 @@
-use std::{path::{PathBuf, Path}, time::Duration};
+use std::{collections::{HashMap, HashSet}, env, fmt, path::{PathBuf, Path}, str::FromStr, thread, time::Duration};
 
+use super::bind::{BindAddress, ListenAddress};
+use super::environment;
+use super::error::ConfigError;
+use super::help;
+use super::overrides::ConfigOverrides;
+use super::rules::{HeaderRule, Redirect, Route};
 use super::util;
 
+// shared with the help page
+pub const DEFAULT_PORT: u16 = 8080;
+pub const DEFAULT_BIND_HOST: &str = "0.0.0.0";
+pub const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o660;
+pub const DEFAULT_SOURCE: &str = "./";
+pub const DEFAULT_TIMEOUT: u64 = 30;
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT: u64 = 5;
+pub const DEFAULT_DRAIN_TIMEOUT: u64 = 10;
+pub const DEFAULT_QUEUE_SIZE: usize = 1024;
+pub const DEFAULT_OVERLOAD_POLICY: OverloadPolicy = OverloadPolicy::Block;
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 1024 * 1024;
+pub const DEFAULT_INDEX_FILE_NAME: &str = "index.html";
+pub const DEFAULT_404_FILE_NAME: &str = "404.html";
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+pub const DEFAULT_COMPRESSION_MIN_SIZE: u64 = 1024;
+
+// a per user cache directory (XDG base directory specification), a shared one like /tmp would let other users
+// prepare entries under the predictable file names
+pub fn default_compression_cache_path() -> PathBuf {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .filter(|path| path.is_absolute());
+
+    match cache_home {
+        Some(cache_home) => cache_home.join("throwscape").join("compression"),
+        // still refused later on if another user created it first
+        None => env::temp_dir().join(format!("throwscape-compression-cache-{}", unsafe { libc::getuid() })),
+    }
+}
+
+// one worker per CPU, falls back to 4 where the count can't be determined
+pub fn default_thread_count() -> u32 {
+    thread::available_parallelism().map(|threads| threads.get() as u32).unwrap_or(4)
+}
+
+// decides what happens to new work while the queue of the thread pool is full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverloadPolicy {
+    // stops accepting connections until there is room again
+    Block,
+    // answers requests with "503 Service Unavailable" and a Retry-After header
+    Reject,
+}
+
+impl FromStr for OverloadPolicy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<OverloadPolicy, ()> {
+        match value {
+            "block" => Ok(OverloadPolicy::Block),
+            "reject" => Ok(OverloadPolicy::Reject),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for OverloadPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverloadPolicy::Block => write!(f, "block"),
+            OverloadPolicy::Reject => write!(f, "reject"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TlsCertificate {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
 #[derive(Clone)]
 pub struct Config {
     pub port: u16,
+    pub bind_addresses: Vec<BindAddress>,
+    pub unix_socket_mode: u32,
     pub serve_path: PathBuf,
     pub timeout: Duration,
+    pub keep_alive_timeout: Duration,
+    pub drain_timeout: Duration,
+    pub threads: u32,
+    pub queue_size: usize,
+    pub overload_policy: OverloadPolicy,
+    pub max_body_size: u64,
     pub index_file_name: String,
     pub file_not_found_path: Box<Path>,
+    pub mime_types: HashMap<String, String>,
+    pub default_mime_type: String,
+    pub is_compression_enabled: bool,
+    pub compression_level: u32,
+    pub compression_min_size: u64,
+    pub compression_cache_path: PathBuf,
+    pub tls_certificates: Vec<TlsCertificate>,
+    pub is_http3_enabled: bool,
     pub is_in_debug_mode: bool,
+    pub routes: Vec<Route>,
+    pub header_rules: Vec<HeaderRule>,
+    pub redirects: Vec<Redirect>,
 }
 
 impl Config {
-    pub fn new(args: &[String]) -> Result<Config, String> {
+    pub fn new(args: &[String]) -> Result<Config, ConfigError> {
+        let env_vars = env::vars()
+            .filter(|(variable, _)| {
+                variable.starts_with(environment::PREFIX) || environment::SYSTEMD_VARIABLES.contains(&variable.as_str())
+            })
+            .collect();
+        Self::from_sources(args, &env_vars)
+    }
+
+    // walks the arguments like the parsing does, so a value that looks like "--help" or "-V" isn't taken for the flag,
+    // help wins over the version
+    fn find_page_request(args: &[String]) -> Option<ConfigError> {
+        let mut is_help_requested = false;
+        let mut is_version_requested = false;
+
         let mut index = 1;
-        let mut port = 8080;
-        let mut serve_path = PathBuf::from("./");
-        let mut timeout = Duration::from_secs(30);
-        let mut index_file_name = String::from("index.html");
+        while index < args.len() {
+            match args[index].as_str() {
+                "--help" | "-h" => is_help_requested = true,
+                "--version" | "-V" => is_version_requested = true,
+                flag if help::takes_value(flag) && !util::check_for_missing_next_value(args, index) => index += 1,
+                _ => {}
+            }
+            index += 1;
+        }
+
+        if is_help_requested {
+            Some(ConfigError::HelpRequested)
+        } else if is_version_requested {
+            Some(ConfigError::VersionRequested)
+        } else {
+            None
+        }
+    }
+
+    fn from_sources(args: &[String], env_vars: &HashMap<String, String>) -> Result<Config, ConfigError> {
+        // checked first, so a broken config file or environment doesn't hide the help page
+        if let Some(page_request) = Self::find_page_request(args) {
+            return Err(page_request);
+        }
+
+        let mut index = 1;
+        let mut port = DEFAULT_PORT;
+        let mut bind_values = vec![];
+        let mut cli_bind_values = vec![];
+        let mut unix_socket_mode = DEFAULT_UNIX_SOCKET_MODE;
+        let mut serve_path = PathBuf::from(DEFAULT_SOURCE);
+        let mut timeout = Duration::from_secs(DEFAULT_TIMEOUT);
+        let mut keep_alive_timeout = Duration::from_secs(DEFAULT_KEEP_ALIVE_TIMEOUT);
+        let mut drain_timeout = Duration::from_secs(DEFAULT_DRAIN_TIMEOUT);
+        let mut threads = default_thread_count();
+        let mut queue_size = DEFAULT_QUEUE_SIZE;
+        let mut overload_policy = DEFAULT_OVERLOAD_POLICY;
+        let mut max_body_size = DEFAULT_MAX_BODY_SIZE;
+        let mut index_file_name = String::from(DEFAULT_INDEX_FILE_NAME);
         let mut file_not_found_path = serve_path.clone();
-        file_not_found_path.push("404.html");
+        file_not_found_path.push(DEFAULT_404_FILE_NAME);
+        let mut mime_types = HashMap::new();
+        let mut default_mime_type = String::from(DEFAULT_MIME_TYPE);
+        let mut is_compression_enabled = false;
+        let mut compression_level = DEFAULT_COMPRESSION_LEVEL;
+        let mut compression_min_size = DEFAULT_COMPRESSION_MIN_SIZE;
+        let mut compression_cache_path = default_compression_cache_path();
+        let mut tls_cert_paths = vec![];
+        let mut tls_key_paths = vec![];
+        let mut is_http3_enabled = false;
         let mut is_in_debug_mode = false;
+        let mut tls_certificates = vec![];
+        let mut routes = vec![];
+        let mut header_rules = vec![];
+        let mut redirects = vec![];
+
+        // precedence is command line over environment over config file over defaults
+        let config_file_path = util::find_config_file_path(args)
+            .or_else(|| env_vars.get(environment::CONFIG_FILE_VARIABLE).map(String::as_str));
+        let mut sources = vec![];
+        if let Some(config_file_path) = config_file_path {
+            sources.push(ConfigOverrides::load_file(Path::new(config_file_path))?);
+        }
+        sources.push(ConfigOverrides::from_env_vars(env_vars)?);
+
+        for overrides in sources {
+            port = overrides.port.unwrap_or(port);
+            if !overrides.bind_addresses.is_empty() {
+                bind_values = overrides.bind_addresses;
+            }
+            unix_socket_mode = overrides.unix_socket_mode.unwrap_or(unix_socket_mode);
+            serve_path = overrides.serve_path.unwrap_or(serve_path);
+            timeout = overrides.timeout.map(Duration::from_secs).unwrap_or(timeout);
+            keep_alive_timeout = overrides.keep_alive_timeout.map(Duration::from_secs).unwrap_or(keep_alive_timeout);
+            drain_timeout = overrides.drain_timeout.map(Duration::from_secs).unwrap_or(drain_timeout);
+            threads = overrides.threads.unwrap_or(threads);
+            queue_size = overrides.queue_size.unwrap_or(queue_size);
+            overload_policy = overrides.overload_policy.unwrap_or(overload_policy);
+            max_body_size = overrides.max_body_size.unwrap_or(max_body_size);
+            index_file_name = overrides.index_file_name.unwrap_or(index_file_name);
+            file_not_found_path = overrides.file_not_found_path.unwrap_or(file_not_found_path);
+            mime_types.extend(overrides.mime_types);
+            default_mime_type = overrides.default_mime_type.unwrap_or(default_mime_type);
+            is_compression_enabled = overrides.is_compression_enabled.unwrap_or(is_compression_enabled);
+            compression_level = overrides.compression_level.unwrap_or(compression_level);
+            compression_min_size = overrides.compression_min_size.unwrap_or(compression_min_size);
+            compression_cache_path = overrides.compression_cache_path.unwrap_or(compression_cache_path);
+            if !overrides.tls_certificates.is_empty() {
+                tls_certificates = overrides.tls_certificates;
+            }
+            is_http3_enabled = overrides.is_http3_enabled.unwrap_or(is_http3_enabled);
+            is_in_debug_mode = overrides.is_in_debug_mode.unwrap_or(is_in_debug_mode);
+            routes.extend(overrides.routes);
+            header_rules.extend(overrides.header_rules);
+            redirects.extend(overrides.redirects);
+        }
 
         while index < args.len() {
             match args[index].as_str() {
                 "--port" => {
-                    if util::check_for_missing_next_value(args, index) {
-                        return Err(String::from("Missing port number"));
-                    }
-
                     port = util::parse_next_arg(args, index)?;
                     index += 1;
-
+                },
+                "--bind" => {
+                    cli_bind_values.push(util::get_next_value(args, index)?.to_string());
+                    index += 1;
+                },
+                "--inherit-fd" => {
+                    let fd: u16 = util::parse_next_arg(args, index)?;
+                    cli_bind_values.push(format!("fd:{}", fd));
+                    index += 1;
+                },
+                "--unix-socket-mode" => {
+                    let mode = util::get_next_value(args, index)?;
+                    unix_socket_mode = util::parse_file_mode(mode)
+                        .ok_or_else(|| ConfigError::InvalidValue { flag: args[index].to_string(), value: mode.to_string() })?;
+                    index += 1;
                 },
                 "--source" => {
-                    if util::check_for_missing_next_value(args, index) {
-                        return Err(String::from("Missing source path"));
-                    }
-
-                    serve_path = PathBuf::from(&args[index + 1]);
+                    serve_path = PathBuf::from(util::get_next_value(args, index)?);
                     index += 1;
                 },
                 "--timeout" => {
-                    if util::check_for_missing_next_value(args, index) {
-                        return Err(String::from("Missing timeout in sec"));
-                    }
-                    
                     timeout = Duration::from_secs(util::parse_next_arg(args, index)?);
                     index += 1;
                 },
+                "--keep-alive-timeout" => {
+                    keep_alive_timeout = Duration::from_secs(util::parse_next_arg(args, index)?);
+                    index += 1;
+                },
+                "--drain-timeout" => {
+                    drain_timeout = Duration::from_secs(util::parse_next_arg(args, index)?);
+                    index += 1;
+                },
+                "--threads" => {
+                    threads = util::parse_next_arg(args, index)?;
+                    index += 1;
+                },
+                "--queue-size" => {
+                    queue_size = util::parse_next_arg(args, index)?;
+                    index += 1;
+                },
+                "--overload-policy" => {
+                    overload_policy = util::parse_next_arg(args, index)?;
+                    index += 1;
+                },
+                "--max-body-size" => {
+                    max_body_size = util::parse_next_arg(args, index)?;
+                    index += 1;
+                },
                 "--index-file-name" => {
-                    if util::check_for_missing_next_value(args, index) {
-                        return Err(String::from("Missing index file name"));
-                    }
-
-                    index_file_name = args[index + 1].to_string();
+                    index_file_name = util::get_next_value(args, index)?.to_string();
                     index += 1;
                 },
                 "--404-file" => {
-                    if util::check_for_missing_next_value(args, index) {
-                        return Err(String::from("Missing 404 file path"));
-                    }
-
-                    file_not_found_path = PathBuf::from(&args[index + 1]);
+                    file_not_found_path = PathBuf::from(util::get_next_value(args, index)?);
+                    index += 1;
+                },
+                "--mime-type" => {
+                    let mapping = util::get_next_value(args, index)?;
+                    let (extension, mime_type) = util::parse_mime_type_mapping(mapping)
+                        .map_err(|_| ConfigError::InvalidValue { flag: args[index].to_string(), value: mapping.to_string() })?;
+                    mime_types.insert(extension, mime_type);
+                    index += 1;
+                },
+                "--default-mime-type" => {
+                    default_mime_type = util::get_next_value(args, index)?.to_string();
+                    index += 1;
+                },
+                "--compression" => {
+                    is_compression_enabled = true;
+                },
+                "--compression-level" => {
+                    compression_level = util::parse_next_arg(args, index)?;
+                    index += 1;
+                },
+                "--compression-min-size" => {
+                    compression_min_size = util::parse_next_arg(args, index)?;
+                    index += 1;
+                },
+                "--compression-cache-dir" => {
+                    compression_cache_path = PathBuf::from(util::get_next_value(args, index)?);
+                    index += 1;
+                },
+                "--tls-cert" => {
+                    tls_cert_paths.push(PathBuf::from(util::get_next_value(args, index)?));
+                    index += 1;
+                },
+                "--tls-key" => {
+                    tls_key_paths.push(PathBuf::from(util::get_next_value(args, index)?));
+                    index += 1;
+                },
+                "--http3" => {
+                    is_http3_enabled = true;
+                },
+                "--config" => {
+                    // already loaded before the other arguments
+                    util::get_next_value(args, index)?;
                     index += 1;
                 },
                 "--debug" => {
                     is_in_debug_mode = true;
                 }
-                _ => return Err(format!("Invalid parameter: \"{}\"", args[index])),
+                _ => return Err(ConfigError::UnknownParameter { flag: args[index].to_string() }),
             }
             index += 1;
         }
 
+        // certificates and keys are paired up in the order they were passed in
+        if tls_cert_paths.len() != tls_key_paths.len() {
+            return Err(ConfigError::UnpairedTlsCertificates {
+                cert_count: tls_cert_paths.len(),
+                key_count: tls_key_paths.len(),
+            });
+        }
+        // certificates from the command line replace the ones of the config file as a whole
+        if !tls_cert_paths.is_empty() {
+            tls_certificates = tls_cert_paths.into_iter()
+                .zip(tls_key_paths)
+                .map(|(cert_path, key_path)| TlsCertificate { cert_path, key_path })
+                .collect();
+        }
+
+        if !cli_bind_values.is_empty() {
+            bind_values = cli_bind_values;
+        }
+        // sockets passed by systemd come on top of the configured ones
+        bind_values.extend(environment::find_systemd_bind_values(env_vars)?);
+        if bind_values.is_empty() {
+            bind_values.push(format!("{}:{}", DEFAULT_BIND_HOST, port));
+        }
+        let mut bind_addresses = vec![];
+        for value in bind_values {
+            let addresses = BindAddress::parse(&value, !tls_certificates.is_empty())
+                .ok_or_else(|| ConfigError::InvalidValue { flag: String::from("--bind"), value: value.clone() })?;
+            if addresses.iter().any(|bind_address| bind_address.is_tls) && tls_certificates.is_empty() {
+                return Err(ConfigError::TlsWithoutCertificate { flag: String::from("--bind"), value });
+            }
+            bind_addresses.extend(addresses);
+        }
+
+        // an inherited socket is closed by its listener, so each one may only be used once
+        let mut inherited_fds = HashSet::new();
+        for bind_address in &bind_addresses {
+            if let ListenAddress::Inherited(fd) = bind_address.address {
+                if !inherited_fds.insert(fd) {
+                    return Err(ConfigError::InvalidValue { flag: String::from("--inherit-fd"), value: fd.to_string() });
+                }
+            }
+        }
+
+        // a pool without workers or queue would never answer anything
+        if threads == 0 {
+            return Err(ConfigError::InvalidValue { flag: String::from("--threads"), value: threads.to_string() });
+        }
+        if queue_size == 0 {
+            return Err(ConfigError::InvalidValue { flag: String::from("--queue-size"), value: queue_size.to_string() });
+        }
+
+        // QUIC always runs encrypted, there is no cleartext HTTP/3
+        if is_http3_enabled && !bind_addresses.iter().any(|bind_address| bind_address.is_tls) {
+            return Err(ConfigError::Http3WithoutTls);
+        }
+
         let serve_path = match serve_path.canonicalize() {
             Ok(serve_path) => serve_path,
-            Err(_) => return Err(String::from("Failed to read directory")),
+            Err(_) => return Err(ConfigError::InvalidSourceDirectory { path: serve_path }),
         };
         
         Ok(Config { 
             port,
+            bind_addresses,
+            unix_socket_mode,
             serve_path,
             timeout,
+            keep_alive_timeout,
+            drain_timeout,
+            threads,
+            queue_size,
+            overload_policy,
+            max_body_size,
             index_file_name,
             file_not_found_path: Box::from(file_not_found_path),
+            mime_types,
+            default_mime_type,
+            is_compression_enabled,
+            compression_level,
+            compression_min_size,
+            compression_cache_path,
+            tls_certificates,
+            is_http3_enabled,
             is_in_debug_mode,
+            routes,
+            header_rules,
+            redirects,
         })
     }
 
@@ -93,18 +430,23 @@ impl Config {
 
 #[cfg(test)]
 mod tests {
-    use std::{time::Duration, path::PathBuf};
+    use std::{env, fs, time::Duration, path::PathBuf};
+
+    use crate::configuration::error::ConfigError;
+    use crate::test_util::TempDirectory;
 
     #[test]
     fn correctly_parses_arguments() {
         const PORT: u16 = 99;
         const TIMEOUT: u64 = 2;
+        const KEEP_ALIVE_TIMEOUT: u64 = 1;
         const SOURCE_FOLDER: &str = "./example";
 
         let args = [
             String::from("throwscape"),
             String::from("--port"), PORT.to_string(),
             String::from("--timeout"), TIMEOUT.to_string(),
+            String::from("--keep-alive-timeout"), KEEP_ALIVE_TIMEOUT.to_string(),
             String::from("--source"), SOURCE_FOLDER.to_string(),
         ];
         
@@ -112,6 +454,160 @@ mod tests {
 
         assert_eq!(result.port, PORT);
         assert_eq!(result.timeout, Duration::from_secs(TIMEOUT));
+        assert_eq!(result.keep_alive_timeout, Duration::from_secs(KEEP_ALIVE_TIMEOUT));
         assert_eq!(result.serve_path, PathBuf::from(SOURCE_FOLDER).canonicalize().unwrap());
     }
+
+    #[test]
+    fn pairs_tls_certificates_with_keys() {
+        let args = [
+            String::from("throwscape"),
+            String::from("--tls-cert"), String::from("a.pem"),
+            String::from("--tls-key"), String::from("a.key"),
+            String::from("--tls-cert"), String::from("b.pem"),
+            String::from("--tls-key"), String::from("b.key"),
+        ];
+
+        let result = super::Config::new(&args).unwrap();
+
+        assert_eq!(result.tls_certificates.len(), 2);
+        assert_eq!(result.tls_certificates[1], super::TlsCertificate {
+            cert_path: PathBuf::from("b.pem"),
+            key_path: PathBuf::from("b.key"),
+        });
+    }
+
+    #[test]
+    fn rejects_tls_certificate_without_key() {
+        let args = [
+            String::from("throwscape"),
+            String::from("--tls-cert"), String::from("a.pem"),
+        ];
+
+        assert!(super::Config::new(&args).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_environment_and_config_file() {
+        let directory = TempDirectory::new("throwscape_config_precedence_test");
+        let config_file_path = directory.join("throwscape.toml");
+        fs::write(&config_file_path, "port = 9000\ntimeout = 10\ncompression = true\n").unwrap();
+
+        let args = [
+            String::from("throwscape"),
+            String::from("--port"), String::from("9001"),
+            String::from("--config"), config_file_path.to_string_lossy().to_string(),
+        ];
+
+        let env_vars = vec![
+            (String::from("THROWSCAPE_PORT"), String::from("9002")),
+            (String::from("THROWSCAPE_TIMEOUT"), String::from("20")),
+        ].into_iter().collect();
+
+        let result = super::Config::from_sources(&args, &env_vars).unwrap();
+
+        assert_eq!(result.port, 9001);
+        assert_eq!(result.timeout, Duration::from_secs(20));
+        assert!(result.is_compression_enabled);
+        assert_eq!(result.keep_alive_timeout, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn help_and_version_are_not_taken_from_values() {
+        let args = [String::from("throwscape"), String::from("--index-file-name"), String::from("-h")];
+        assert_eq!(super::Config::new(&args).unwrap().index_file_name, "-h");
+
+        let args = [String::from("throwscape"), String::from("--tls-cert"), String::from("-V"), String::from("--version")];
+        assert_eq!(super::Config::new(&args).err(), Some(ConfigError::VersionRequested));
+
+        // a flag can't be the value of another one
+        let args = [String::from("throwscape"), String::from("--source"), String::from("--help")];
+        assert_eq!(super::Config::new(&args).err(), Some(ConfigError::HelpRequested));
+
+        let args = [String::from("throwscape"), String::from("-V"), String::from("-h")];
+        assert_eq!(super::Config::new(&args).err(), Some(ConfigError::HelpRequested));
+    }
+
+    #[test]
+    fn leaves_environment_untouched() {
+        let env_vars: Vec<(String, String)> = env::vars().collect();
+        super::Config::new(&[String::from("throwscape")]).unwrap();
+        assert_eq!(env::vars().collect::<Vec<(String, String)>>(), env_vars);
+    }
+
+    #[test]
+    fn binds_to_every_given_address() {
+        let args = [
+            String::from("throwscape"),
+            String::from("--port"), String::from("9000"),
+            String::from("--bind"), String::from("127.0.0.1:8080"),
+            String::from("--bind"), String::from("[::]:8080"),
+        ];
+
+        let result = super::Config::new(&args).unwrap();
+        let addresses: Vec<_> = result.bind_addresses.iter().map(|bind_address| bind_address.address.to_string()).collect();
+        assert_eq!(addresses, vec!["127.0.0.1:8080", "[::]:8080"]);
+
+        let result = super::Config::new(&args[..3]).unwrap();
+        assert_eq!(result.bind_addresses[0].address.to_string(), "0.0.0.0:9000");
+
+        let args = [String::from("throwscape"), String::from("--bind"), String::from("https://[::]:8443")];
+        assert_eq!(super::Config::new(&args).err(), Some(super::ConfigError::TlsWithoutCertificate {
+            flag: String::from("--bind"),
+            value: String::from("https://[::]:8443"),
+        }));
+    }
+
+    #[test]
+    fn inherited_sockets_replace_the_default_address() {
+        let pid = std::process::id().to_string();
+        let env_vars = vec![
+            (String::from("LISTEN_PID"), pid),
+            (String::from("LISTEN_FDS"), String::from("1")),
+        ].into_iter().collect();
+
+        let args = [String::from("throwscape"), String::from("--inherit-fd"), String::from("5")];
+        let result = super::Config::from_sources(&args, &env_vars).unwrap();
+        let addresses: Vec<_> = result.bind_addresses.iter().map(|bind_address| bind_address.address.to_string()).collect();
+        assert_eq!(addresses, vec!["fd:5", "fd:3"]);
+
+        let args = [String::from("throwscape"), String::from("--inherit-fd"), String::from("3")];
+        assert!(super::Config::from_sources(&args, &env_vars).is_err());
+    }
+
+    #[test]
+    fn rejects_http3_without_tls() {
+        let args = [
+            String::from("throwscape"),
+            String::from("--http3"),
+        ];
+
+        assert!(super::Config::new(&args).is_err());
+    }
+
+    #[test]
+    fn sizes_the_thread_pool() {
+        let result = super::Config::new(&[String::from("throwscape")]).unwrap();
+        assert_eq!(result.threads, super::default_thread_count());
+        assert_eq!(result.overload_policy, super::OverloadPolicy::Block);
+
+        let args = [
+            String::from("throwscape"),
+            String::from("--threads"), String::from("2"),
+            String::from("--queue-size"), String::from("16"),
+            String::from("--overload-policy"), String::from("reject"),
+        ];
+        let result = super::Config::new(&args).unwrap();
+        assert_eq!(result.threads, 2);
+        assert_eq!(result.queue_size, 16);
+        assert_eq!(result.overload_policy, super::OverloadPolicy::Reject);
+
+        let args = [String::from("throwscape"), String::from("--queue-size"), String::from("0")];
+        assert_eq!(super::Config::new(&args).err(), Some(super::ConfigError::InvalidValue {
+            flag: String::from("--queue-size"),
+            value: String::from("0"),
+        }));
+        let args = [String::from("throwscape"), String::from("--overload-policy"), String::from("drop")];
+        assert!(super::Config::new(&args).is_err());
+    }
 }
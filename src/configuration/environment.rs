@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+
+use super::error::ConfigError;
+use super::overrides::ConfigOverrides;
+use super::{util, TlsCertificate};
+
+pub const PREFIX: &str = "THROWSCAPE_";
+pub const CONFIG_FILE_VARIABLE: &str = "THROWSCAPE_CONFIG";
+// set by systemd socket activation, see sd_listen_fds(3)
+pub const SYSTEMD_VARIABLES: [&str; 3] = ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"];
+const SYSTEMD_FIRST_FD: i32 = 3;
+
+// just like sd_listen_fds(3) does, so processes started later on don't see them,
+// changes the environment of the whole process so it has to happen before any other thread is started
+pub fn unset_systemd_variables() {
+    for variable in SYSTEMD_VARIABLES.iter() {
+        env::remove_var(variable);
+    }
+}
+
+impl ConfigOverrides {
+    // variables are named after the command line flags, ex. "--keep-alive-timeout" becomes "THROWSCAPE_KEEP_ALIVE_TIMEOUT",
+    // list values (mime types, certificates and keys) are separated by commas
+    pub fn from_env_vars(env_vars: &HashMap<String, String>) -> Result<ConfigOverrides, ConfigError> {
+        let mut overrides = ConfigOverrides::default();
+        let mut tls_cert_paths = vec![];
+        let mut tls_key_paths = vec![];
+        let mut bind_values = vec![];
+        let mut inherited_fd_values = vec![];
+
+        for (variable, value) in env_vars {
+            let name = match variable.strip_prefix(PREFIX) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            match name {
+                "PORT" => overrides.port = Some(parse_value(variable, value)?),
+                "BIND" => bind_values.extend(split_list(value).map(String::from)),
+                "INHERIT_FD" => {
+                    for fd in split_list(value) {
+                        let fd: u16 = parse_value(variable, fd)?;
+                        inherited_fd_values.push(format!("fd:{}", fd));
+                    }
+                }
+                "UNIX_SOCKET_MODE" => {
+                    overrides.unix_socket_mode = Some(util::parse_file_mode(value).ok_or_else(|| invalid_value(variable, value))?)
+                }
+                "SOURCE" => overrides.serve_path = Some(PathBuf::from(value)),
+                "TIMEOUT" => overrides.timeout = Some(parse_value(variable, value)?),
+                "KEEP_ALIVE_TIMEOUT" => overrides.keep_alive_timeout = Some(parse_value(variable, value)?),
+                "DRAIN_TIMEOUT" => overrides.drain_timeout = Some(parse_value(variable, value)?),
+                "THREADS" => overrides.threads = Some(parse_value(variable, value)?),
+                "QUEUE_SIZE" => overrides.queue_size = Some(parse_value(variable, value)?),
+                "OVERLOAD_POLICY" => overrides.overload_policy = Some(parse_value(variable, value)?),
+                "MAX_BODY_SIZE" => overrides.max_body_size = Some(parse_value(variable, value)?),
+                "INDEX_FILE_NAME" => overrides.index_file_name = Some(value.clone()),
+                "404_FILE" => overrides.file_not_found_path = Some(PathBuf::from(value)),
+                "MIME_TYPE" => {
+                    for mapping in split_list(value) {
+                        let (extension, mime_type) = util::parse_mime_type_mapping(mapping)
+                            .map_err(|_| invalid_value(variable, value))?;
+                        overrides.mime_types.insert(extension, mime_type);
+                    }
+                }
+                "DEFAULT_MIME_TYPE" => overrides.default_mime_type = Some(value.clone()),
+                "COMPRESSION" => overrides.is_compression_enabled = Some(parse_flag(variable, value)?),
+                "COMPRESSION_LEVEL" => overrides.compression_level = Some(parse_value(variable, value)?),
+                "COMPRESSION_MIN_SIZE" => overrides.compression_min_size = Some(parse_value(variable, value)?),
+                "COMPRESSION_CACHE_DIR" => overrides.compression_cache_path = Some(PathBuf::from(value)),
+                "TLS_CERT" => tls_cert_paths.extend(split_list(value).map(PathBuf::from)),
+                "TLS_KEY" => tls_key_paths.extend(split_list(value).map(PathBuf::from)),
+                "HTTP3" => overrides.is_http3_enabled = Some(parse_flag(variable, value)?),
+                "DEBUG" => overrides.is_in_debug_mode = Some(parse_flag(variable, value)?),
+                // read before any other source, as it decides whether there is a config file at all
+                "CONFIG" => {}
+                // orchestrators add variables of their own, ex. kubernetes service links like "THROWSCAPE_SERVICE_HOST"
+                _ => {}
+            }
+        }
+
+        // the variables come in no particular order, the addresses always do
+        overrides.bind_addresses.extend(bind_values);
+        overrides.bind_addresses.extend(inherited_fd_values);
+
+        if tls_cert_paths.len() != tls_key_paths.len() {
+            return Err(ConfigError::UnpairedTlsCertificates {
+                cert_count: tls_cert_paths.len(),
+                key_count: tls_key_paths.len(),
+            });
+        }
+        overrides.tls_certificates = tls_cert_paths
+            .into_iter()
+            .zip(tls_key_paths)
+            .map(|(cert_path, key_path)| TlsCertificate { cert_path, key_path })
+            .collect();
+
+        Ok(overrides)
+    }
+}
+
+// the sockets follow right after stdin, stdout and stderr, named "http" or "https" (FileDescriptorName=) they pick the protocol,
+// they're meant for another process if the pid doesn't match, ex. a shell script that started throwscape
+pub fn find_systemd_bind_values(env_vars: &HashMap<String, String>) -> Result<Vec<String>, ConfigError> {
+    match env_vars.get("LISTEN_PID") {
+        Some(pid) if pid.trim() == process::id().to_string() => {}
+        _ => return Ok(vec![]),
+    }
+
+    let fd_count: u16 = match env_vars.get("LISTEN_FDS") {
+        Some(fd_count) => parse_value("LISTEN_FDS", fd_count)?,
+        None => 0,
+    };
+    let names: Vec<&str> = match env_vars.get("LISTEN_FDNAMES") {
+        Some(names) => names.split(':').collect(),
+        None => vec![],
+    };
+
+    let bind_values = (0..fd_count)
+        .map(|offset| {
+            let fd = SYSTEMD_FIRST_FD + i32::from(offset);
+            match names.get(usize::from(offset)) {
+                Some(&"https") => format!("https://fd:{}", fd),
+                Some(&"http") => format!("http://fd:{}", fd),
+                _ => format!("fd:{}", fd),
+            }
+        })
+        .collect();
+    Ok(bind_values)
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|entry| !entry.is_empty())
+}
+
+fn parse_value<T: FromStr>(variable: &str, value: &str) -> Result<T, ConfigError> {
+    value.trim().parse::<T>().map_err(|_| invalid_value(variable, value))
+}
+
+fn parse_flag(variable: &str, value: &str) -> Result<bool, ConfigError> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err(invalid_value(variable, value)),
+    }
+}
+
+fn invalid_value(variable: &str, value: &str) -> ConfigError {
+    ConfigError::InvalidEnvironmentVariable {
+        variable: String::from(variable),
+        value: String::from(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use crate::configuration::error::ConfigError;
+    use crate::configuration::overrides::ConfigOverrides;
+
+    fn env_vars(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn reads_prefixed_variables() {
+        let vars = env_vars(&[
+            ("THROWSCAPE_PORT", "9000"),
+            ("THROWSCAPE_COMPRESSION", "yes"),
+            ("THROWSCAPE_MIME_TYPE", "wasm=application/wasm, .map=application/json"),
+            ("THROWSCAPE_TLS_CERT", "a.pem,b.pem"),
+            ("THROWSCAPE_TLS_KEY", "a.key,b.key"),
+            ("THROWSCAPE_SERVICE_HOST", "10.0.0.1"),
+            ("PORT", "1"),
+        ]);
+
+        let result = ConfigOverrides::from_env_vars(&vars).unwrap();
+
+        assert_eq!(result.port, Some(9000));
+        assert_eq!(result.is_compression_enabled, Some(true));
+        assert_eq!(result.mime_types.get("map").map(String::as_str), Some("application/json"));
+        assert_eq!(result.tls_certificates[1].key_path, PathBuf::from("b.key"));
+    }
+
+    #[test]
+    fn lists_bind_addresses_before_inherited_sockets() {
+        // every map iterates in an order of its own
+        for _ in 0..16 {
+            let vars = env_vars(&[("THROWSCAPE_INHERIT_FD", "5, 6"), ("THROWSCAPE_BIND", "127.0.0.1:8080")]);
+            let result = ConfigOverrides::from_env_vars(&vars).unwrap();
+            assert_eq!(result.bind_addresses, vec!["127.0.0.1:8080", "fd:5", "fd:6"]);
+        }
+    }
+
+    #[test]
+    fn reads_systemd_sockets_for_this_process_only() {
+        let pid = std::process::id().to_string();
+        let vars = env_vars(&[("LISTEN_PID", &pid), ("LISTEN_FDS", "2"), ("LISTEN_FDNAMES", "web:https")]);
+        assert_eq!(super::find_systemd_bind_values(&vars), Ok(vec![String::from("fd:3"), String::from("https://fd:4")]));
+
+        let vars = env_vars(&[("LISTEN_PID", "1"), ("LISTEN_FDS", "2")]);
+        assert_eq!(super::find_systemd_bind_values(&vars), Ok(vec![]));
+    }
+
+    #[test]
+    fn invalid_values_name_the_variable() {
+        let result = ConfigOverrides::from_env_vars(&env_vars(&[("THROWSCAPE_DEBUG", "maybe")]));
+        assert_eq!(
+            result.err(),
+            Some(ConfigError::InvalidEnvironmentVariable {
+                variable: String::from("THROWSCAPE_DEBUG"),
+                value: String::from("maybe"),
+            })
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    // not actual errors, but they stop the server from starting just the same
+    HelpRequested,
+    VersionRequested,
+    UnknownParameter {
+        flag: String,
+    },
+    MissingValue {
+        flag: String,
+    },
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    InvalidEnvironmentVariable {
+        variable: String,
+        value: String,
+    },
+    UnpairedTlsCertificates {
+        cert_count: usize,
+        key_count: usize,
+    },
+    Http3WithoutTls,
+    TlsWithoutCertificate {
+        flag: String,
+        value: String,
+    },
+    InvalidSourceDirectory {
+        path: PathBuf,
+    },
+    UnreadableConfigFile {
+        path: PathBuf,
+        reason: String,
+    },
+    // line and key point to the offending value, if it could be located
+    InvalidConfigFile {
+        path: PathBuf,
+        line: Option<usize>,
+        key: Option<String>,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::HelpRequested => write!(f, "Help requested"),
+            ConfigError::VersionRequested => write!(f, "Version requested"),
+            ConfigError::UnknownParameter { flag } => write!(f, "Invalid parameter: \"{}\"", flag),
+            ConfigError::MissingValue { flag } => write!(f, "Missing value for {}", flag),
+            ConfigError::InvalidValue { flag, value } => write!(f, "Invalid value for {}: \"{}\"", flag, value),
+            ConfigError::InvalidEnvironmentVariable { variable, value } => {
+                write!(f, "Invalid value for {}: \"{}\"", variable, value)
+            }
+            ConfigError::UnpairedTlsCertificates { cert_count, key_count } => write!(
+                f,
+                "Every tls certificate needs exactly one matching key, got {} certificates and {} keys",
+                cert_count, key_count
+            ),
+            ConfigError::Http3WithoutTls => write!(f, "HTTP/3 requires a tls certificate"),
+            ConfigError::TlsWithoutCertificate { flag, value } => {
+                write!(f, "{} \"{}\" requires a tls certificate", flag, value)
+            }
+            ConfigError::InvalidSourceDirectory { path } => {
+                write!(f, "Failed to read directory: \"{}\"", path.display())
+            }
+            ConfigError::UnreadableConfigFile { path, reason } => {
+                write!(f, "Failed to read config file {}: {}", path.display(), reason)
+            }
+            ConfigError::InvalidConfigFile { path, line, key, reason } => match (line, key) {
+                (Some(line), Some(key)) => write!(f, "{}, line {}, key \"{}\": {}", path.display(), line, key, reason),
+                (Some(line), None) => write!(f, "{}, line {}: {}", path.display(), line, reason),
+                _ => write!(f, "Invalid config file {}: {}", path.display(), reason),
+            },
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use super::error::ConfigError;
+use super::overrides::ConfigOverrides;
+use super::rules::{HeaderRule, Redirect, Route, REDIRECT_STATUS_CODES};
+use super::{util, TlsCertificate};
+
+// keys mirror the command line flags, relative paths are resolved from the directory of the file
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RawConfigOverrides {
+    port: Option<u16>,
+    #[serde(default)]
+    bind: Vec<String>,
+    #[serde(default)]
+    inherit_fd: Vec<u16>,
+    unix_socket_mode: Option<Spanned<String>>,
+    source: Option<PathBuf>,
+    timeout: Option<u64>,
+    keep_alive_timeout: Option<u64>,
+    drain_timeout: Option<u64>,
+    threads: Option<u32>,
+    queue_size: Option<usize>,
+    overload_policy: Option<Spanned<String>>,
+    max_body_size: Option<u64>,
+    index_file_name: Option<String>,
+    #[serde(rename = "404-file")]
+    file_not_found_path: Option<PathBuf>,
+    #[serde(default)]
+    mime_types: HashMap<String, Spanned<String>>,
+    default_mime_type: Option<String>,
+    compression: Option<bool>,
+    compression_level: Option<u32>,
+    compression_min_size: Option<u64>,
+    compression_cache_dir: Option<PathBuf>,
+    #[serde(default)]
+    tls: Vec<RawTlsCertificate>,
+    http3: Option<bool>,
+    debug: Option<bool>,
+    #[serde(default)]
+    routes: Vec<RawRoute>,
+    #[serde(default)]
+    headers: Vec<RawHeaderRule>,
+    #[serde(default)]
+    redirects: Vec<RawRedirect>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTlsCertificate {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRoute {
+    path: Spanned<String>,
+    source: Spanned<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHeaderRule {
+    path: Spanned<String>,
+    values: BTreeMap<String, Spanned<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRedirect {
+    from: Spanned<String>,
+    to: Spanned<String>,
+    status: Option<Spanned<u16>>,
+}
+
+impl ConfigOverrides {
+    pub fn load_file(path: &Path) -> Result<ConfigOverrides, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|err| ConfigError::UnreadableConfigFile {
+            path: path.to_path_buf(),
+            reason: err.to_string(),
+        })?;
+        Self::parse_file(&content, path)
+    }
+
+    fn parse_file(content: &str, path: &Path) -> Result<ConfigOverrides, ConfigError> {
+        let error_at = |span: Option<Range<usize>>, message: &str| match span {
+            Some(span) => format_error(content, path, span, message),
+            None => ConfigError::InvalidConfigFile {
+                path: path.to_path_buf(),
+                line: None,
+                key: None,
+                reason: String::from(message),
+            },
+        };
+
+        let raw: RawConfigOverrides = toml::from_str(content).map_err(|err| error_at(err.span(), err.message()))?;
+        let base_path = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let unix_socket_mode = match raw.unix_socket_mode {
+            Some(mode) => Some(
+                util::parse_file_mode(mode.get_ref()).ok_or_else(|| error_at(Some(mode.span()), "Invalid file mode"))?,
+            ),
+            None => None,
+        };
+        let overload_policy = match raw.overload_policy {
+            Some(policy) => Some(
+                policy.get_ref().parse().map_err(|_| error_at(Some(policy.span()), "Expected \"block\" or \"reject\""))?,
+            ),
+            None => None,
+        };
+
+        let mut mime_types = HashMap::new();
+        for (extension, mime_type) in raw.mime_types {
+            let (extension, mime_type) = util::parse_mime_type_mapping(&format!("{}={}", extension, mime_type.get_ref()))
+                .map_err(|err| error_at(Some(mime_type.span()), &err))?;
+            mime_types.insert(extension, mime_type);
+        }
+
+        let routes = raw
+            .routes
+            .into_iter()
+            .map(|route| {
+                let serve_path = base_path
+                    .join(route.source.get_ref())
+                    .canonicalize()
+                    .map_err(|_| error_at(Some(route.source.span()), "Failed to read directory"))?;
+                Ok(Route {
+                    path: validate_url_path(route.path, &error_at)?,
+                    serve_path,
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        let header_rules = raw
+            .headers
+            .into_iter()
+            .map(|rule| {
+                let headers = rule
+                    .values
+                    .into_iter()
+                    .map(|(field_name, field_value)| {
+                        if !util::is_valid_header(&field_name, field_value.get_ref()) {
+                            return Err(error_at(Some(field_value.span()), "Invalid header"));
+                        }
+                        Ok((field_name, field_value.into_inner()))
+                    })
+                    .collect::<Result<Vec<_>, ConfigError>>()?;
+                Ok(HeaderRule {
+                    path: validate_url_path(rule.path, &error_at)?,
+                    headers,
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        let redirects = raw
+            .redirects
+            .into_iter()
+            .map(|redirect| {
+                let status = match redirect.status {
+                    Some(status) if !REDIRECT_STATUS_CODES.contains(status.get_ref()) => {
+                        return Err(error_at(Some(status.span()), "Invalid redirect status code"))
+                    }
+                    Some(status) => status.into_inner(),
+                    None => 301,
+                };
+                if !util::is_valid_header("Location", redirect.to.get_ref()) {
+                    return Err(error_at(Some(redirect.to.span()), "Invalid redirect target"));
+                }
+                Ok(Redirect {
+                    from: validate_url_path(redirect.from, &error_at)?,
+                    to: redirect.to.into_inner(),
+                    status,
+                })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        Ok(ConfigOverrides {
+            port: raw.port,
+            bind_addresses: raw.bind.into_iter().chain(raw.inherit_fd.iter().map(|fd| format!("fd:{}", fd))).collect(),
+            unix_socket_mode,
+            serve_path: raw.source.map(|source| base_path.join(source)),
+            timeout: raw.timeout,
+            keep_alive_timeout: raw.keep_alive_timeout,
+            drain_timeout: raw.drain_timeout,
+            threads: raw.threads,
+            queue_size: raw.queue_size,
+            overload_policy,
+            max_body_size: raw.max_body_size,
+            index_file_name: raw.index_file_name,
+            file_not_found_path: raw.file_not_found_path.map(|file_path| base_path.join(file_path)),
+            mime_types,
+            default_mime_type: raw.default_mime_type,
+            is_compression_enabled: raw.compression,
+            compression_level: raw.compression_level,
+            compression_min_size: raw.compression_min_size,
+            compression_cache_path: raw.compression_cache_dir.map(|cache_path| base_path.join(cache_path)),
+            tls_certificates: raw
+                .tls
+                .into_iter()
+                .map(|tls| TlsCertificate {
+                    cert_path: base_path.join(tls.cert),
+                    key_path: base_path.join(tls.key),
+                })
+                .collect(),
+            is_http3_enabled: raw.http3,
+            is_in_debug_mode: raw.debug,
+            routes,
+            header_rules,
+            redirects,
+        })
+    }
+}
+
+fn validate_url_path<F>(path: Spanned<String>, error_at: &F) -> Result<String, ConfigError>
+where
+    F: Fn(Option<Range<usize>>, &str) -> ConfigError,
+{
+    if path.get_ref().starts_with('/') {
+        Ok(path.into_inner())
+    } else {
+        Err(error_at(Some(path.span()), "Paths have to start with \"/\""))
+    }
+}
+
+// ex. "throwscape.toml, line 4, key \"routes.source\": Failed to read directory"
+fn format_error(content: &str, path: &Path, span: Range<usize>, message: &str) -> ConfigError {
+    let start = span.start.min(content.len());
+    let line_number = content[..start].matches('\n').count() + 1;
+    let line_start = content[..start].rfind('\n').map(|index| index + 1).unwrap_or(0);
+    let line = content[line_start..].lines().next().unwrap_or("");
+
+    // the key is taken from the line itself, prefixed by the table it's defined in
+    let table = content[..line_start]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| line.starts_with('['))
+        .map(|line| line.trim_matches(|character| character == '[' || character == ']').trim());
+    let key = line
+        .split_once('=')
+        .map(|(key, _)| key.trim().trim_matches('"'))
+        .filter(|key| !key.is_empty())
+        .map(|key| match table {
+            Some(table) => format!("{}.{}", table, key),
+            None => String::from(key),
+        });
+
+    ConfigError::InvalidConfigFile {
+        path: path.to_path_buf(),
+        line: Some(line_number),
+        key,
+        reason: String::from(message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use crate::configuration::overrides::ConfigOverrides;
+
+    fn parse(content: &str) -> Result<ConfigOverrides, String> {
+        ConfigOverrides::parse_file(content, Path::new("./throwscape.toml")).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn parses_settings_and_sections() {
+        let content = r#"
+port = 8443
+source = "example"
+compression = true
+
+[mime-types]
+".WASM" = "application/wasm"
+
+[[tls]]
+cert = "/certs/example.pem"
+key = "/certs/example.key"
+
+[[routes]]
+path = "/docs"
+source = "src"
+
+[[headers]]
+path = "/"
+values = { "Cache-Control" = "max-age=60" }
+
+[[redirects]]
+from = "/old/*"
+to = "/new/*"
+status = 308
+"#;
+        let result = parse(content).unwrap();
+
+        assert_eq!(result.port, Some(8443));
+        assert_eq!(result.serve_path, Some(PathBuf::from("./example")));
+        assert_eq!(result.is_compression_enabled, Some(true));
+        assert_eq!(result.mime_types.get("wasm").map(String::as_str), Some("application/wasm"));
+        assert_eq!(result.tls_certificates[0].key_path, PathBuf::from("/certs/example.key"));
+        assert_eq!(result.routes[0].serve_path, PathBuf::from("src").canonicalize().unwrap());
+        assert_eq!(result.header_rules[0].headers, vec![(String::from("Cache-Control"), String::from("max-age=60"))]);
+        assert_eq!(result.redirects[0].status, 308);
+    }
+
+    #[test]
+    fn type_errors_point_to_line_and_key() {
+        let result = parse("debug = true\nport = \"80\"\n");
+        let err = result.err().unwrap();
+        assert!(err.starts_with("./throwscape.toml, line 2, key \"port\":"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let result = parse("[[routes]]\npath = \"/docs\"\nsorce = \"src\"\n");
+        let err = result.err().unwrap();
+        assert!(err.starts_with("./throwscape.toml, line 3, key \"routes.sorce\":"), "{}", err);
+    }
+
+    #[test]
+    fn validation_errors_point_to_line_and_key() {
+        let result = parse("[[redirects]]\nfrom = \"/old\"\nto = \"/new\"\nstatus = 200\n");
+        assert_eq!(
+            result.err(),
+            Some(String::from("./throwscape.toml, line 4, key \"redirects.status\": Invalid redirect status code"))
+        );
+    }
+}
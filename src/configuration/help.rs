@@ -0,0 +1,136 @@
+use super::config::{
+    default_compression_cache_path, DEFAULT_404_FILE_NAME, DEFAULT_COMPRESSION_LEVEL, DEFAULT_COMPRESSION_MIN_SIZE, DEFAULT_DRAIN_TIMEOUT,
+    DEFAULT_BIND_HOST, DEFAULT_INDEX_FILE_NAME, DEFAULT_UNIX_SOCKET_MODE, DEFAULT_KEEP_ALIVE_TIMEOUT, DEFAULT_MIME_TYPE, DEFAULT_PORT, DEFAULT_SOURCE,
+    DEFAULT_TIMEOUT, DEFAULT_QUEUE_SIZE, DEFAULT_OVERLOAD_POLICY, DEFAULT_MAX_BODY_SIZE,
+};
+use super::environment;
+
+struct OptionHelp {
+    flag: &'static str,
+    value_name: Option<&'static str>,
+    description: &'static str,
+    default: Option<String>,
+}
+
+impl OptionHelp {
+    fn new(flag: &'static str, value_name: Option<&'static str>, description: &'static str) -> OptionHelp {
+        OptionHelp { flag, value_name, description, default: None }
+    }
+
+    fn with_default<T: ToString>(mut self, default: T) -> OptionHelp {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    fn usage(&self) -> String {
+        match self.value_name {
+            Some(value_name) => format!("{} <{}>", self.flag, value_name),
+            None => String::from(self.flag),
+        }
+    }
+}
+
+// every flag accepted by Config::new, in the order of the readme
+fn list_options() -> Vec<OptionHelp> {
+    vec![
+        OptionHelp::new("--source", Some("PATH"), "Directory to serve files from").with_default(DEFAULT_SOURCE),
+        OptionHelp::new("--port", Some("PORT"), "Port to listen on").with_default(DEFAULT_PORT),
+        OptionHelp::new("--bind", Some("ADDRESS"), "Address to listen on, ex. [::]:8080 or unix:/run/throwscape.sock, repeatable")
+            .with_default(format!("{}:<PORT>", DEFAULT_BIND_HOST)),
+        OptionHelp::new("--inherit-fd", Some("FD"), "Already listening socket to take over, repeatable"),
+        OptionHelp::new("--unix-socket-mode", Some("MODE"), "Permissions of unix sockets bound with --bind unix:PATH")
+            .with_default(format!("{:o}", DEFAULT_UNIX_SOCKET_MODE)),
+        OptionHelp::new("--timeout", Some("SECONDS"), "Time to wait for a request to arrive").with_default(DEFAULT_TIMEOUT),
+        OptionHelp::new("--keep-alive-timeout", Some("SECONDS"), "Time an idle persistent connection is kept open")
+            .with_default(DEFAULT_KEEP_ALIVE_TIMEOUT),
+        OptionHelp::new("--drain-timeout", Some("SECONDS"), "Time running requests get to finish when shutting down")
+            .with_default(DEFAULT_DRAIN_TIMEOUT),
+        OptionHelp::new("--threads", Some("COUNT"), "Worker threads answering requests").with_default("number of CPUs"),
+        OptionHelp::new("--queue-size", Some("COUNT"), "Requests waiting for a worker before the overload policy applies")
+            .with_default(DEFAULT_QUEUE_SIZE),
+        OptionHelp::new("--overload-policy", Some("POLICY"), "block (stop accepting) or reject (503) while the queue is full")
+            .with_default(DEFAULT_OVERLOAD_POLICY),
+        OptionHelp::new("--max-body-size", Some("BYTES"), "Largest request body that is read, larger ones get a 413")
+            .with_default(DEFAULT_MAX_BODY_SIZE),
+        OptionHelp::new("--index-file-name", Some("NAME"), "File served for directory paths")
+            .with_default(DEFAULT_INDEX_FILE_NAME),
+        OptionHelp::new("--404-file", Some("PATH"), "File served when nothing was found")
+            .with_default(format!("{}{}", DEFAULT_SOURCE, DEFAULT_404_FILE_NAME)),
+        OptionHelp::new("--mime-type", Some("EXTENSION=TYPE"), "Adds or overrides a mime type mapping, repeatable"),
+        OptionHelp::new("--default-mime-type", Some("TYPE"), "Mime type of files with unknown extensions")
+            .with_default(DEFAULT_MIME_TYPE),
+        OptionHelp::new("--compression", None, "Compresses text based files on the fly"),
+        OptionHelp::new("--compression-level", Some("LEVEL"), "Compression level, 0-9 for gzip and 0-11 for brotli")
+            .with_default(DEFAULT_COMPRESSION_LEVEL),
+        OptionHelp::new("--compression-min-size", Some("BYTES"), "Smallest file size worth compressing")
+            .with_default(DEFAULT_COMPRESSION_MIN_SIZE),
+        OptionHelp::new("--compression-cache-dir", Some("PATH"), "Directory for cached compressed files")
+            .with_default(default_compression_cache_path().display()),
+        OptionHelp::new("--tls-cert", Some("PATH"), "PEM certificate chain, repeatable and paired with --tls-key"),
+        OptionHelp::new("--tls-key", Some("PATH"), "PEM private key, repeatable and paired with --tls-cert"),
+        OptionHelp::new("--http3", None, "Serves HTTP/3 over QUIC as well, requires TLS"),
+        OptionHelp::new("--config", Some("PATH"), "TOML config file with further settings and rules"),
+        OptionHelp::new("--debug", None, "Logs requests and errors"),
+        OptionHelp::new("--help", None, "Prints this help page"),
+        OptionHelp::new("--version", None, "Prints the version"),
+    ]
+}
+
+// ex. "--index-file-name -h" sets the index file name instead of asking for help
+pub fn takes_value(flag: &str) -> bool {
+    list_options().iter().any(|option| option.flag == flag && option.value_name.is_some())
+}
+
+pub fn generate_version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+pub fn generate_help() -> String {
+    let options = list_options();
+    let usage_width = options.iter().map(|option| option.usage().len()).max().unwrap_or(0);
+
+    let mut help = format!(
+        "{}\nA static webserver designed for containers\n\nUsage: throwscape [OPTIONS]\n\nOptions:\n",
+        generate_version()
+    );
+    for option in &options {
+        help.push_str(&format!("  {:width$}  {}", option.usage(), option.description, width = usage_width));
+        if let Some(default) = &option.default {
+            help.push_str(&format!(" [default: {}]", default));
+        }
+        help.push('\n');
+    }
+    help.push_str(&format!(
+        "\nEvery option can also be set with a {}<OPTION> environment variable, ex. {}KEEP_ALIVE_TIMEOUT=10\n",
+        environment::PREFIX,
+        environment::PREFIX
+    ));
+
+    help
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::error::ConfigError;
+    use crate::configuration::Config;
+
+    #[test]
+    fn every_listed_option_is_accepted() {
+        for option in super::list_options() {
+            let mut args = vec![String::from("throwscape"), String::from(option.flag)];
+            if option.value_name.is_some() {
+                args.push(String::from("x"));
+            }
+
+            let result = Config::new(&args).err();
+            assert_ne!(result, Some(ConfigError::UnknownParameter { flag: String::from(option.flag) }));
+        }
+    }
+
+    #[test]
+    fn help_lists_defaults() {
+        let help = super::generate_help();
+        assert!(help.contains("--port <PORT>"));
+        assert!(help.contains("[default: 8080]"));
+    }
+}
@@ -1,4 +1,17 @@
 mod util;
+mod bind;
 mod config;
+mod environment;
+mod error;
+mod file;
+mod help;
+mod overrides;
+mod rules;
 
-pub use config::Config;
+pub use bind::{BindAddress, ListenAddress};
+pub use config::{Config, OverloadPolicy, TlsCertificate};
+pub use environment::unset_systemd_variables;
+pub use error::ConfigError;
+pub use help::{generate_help, generate_version};
+pub use rules::{HeaderRule, Redirect, Route};
+pub use util::is_valid_header;
@@ -0,0 +1,35 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::rules::{HeaderRule, Redirect, Route};
+use super::{OverloadPolicy, TlsCertificate};
+
+// settings of a single source (ex. config file or environment), unset values keep the ones of the sources before
+#[derive(Default)]
+pub struct ConfigOverrides {
+    pub port: Option<u16>,
+    pub bind_addresses: Vec<String>,
+    pub unix_socket_mode: Option<u32>,
+    pub serve_path: Option<PathBuf>,
+    pub timeout: Option<u64>,
+    pub keep_alive_timeout: Option<u64>,
+    pub drain_timeout: Option<u64>,
+    pub threads: Option<u32>,
+    pub queue_size: Option<usize>,
+    pub overload_policy: Option<OverloadPolicy>,
+    pub max_body_size: Option<u64>,
+    pub index_file_name: Option<String>,
+    pub file_not_found_path: Option<PathBuf>,
+    pub mime_types: HashMap<String, String>,
+    pub default_mime_type: Option<String>,
+    pub is_compression_enabled: Option<bool>,
+    pub compression_level: Option<u32>,
+    pub compression_min_size: Option<u64>,
+    pub compression_cache_path: Option<PathBuf>,
+    pub tls_certificates: Vec<TlsCertificate>,
+    pub is_http3_enabled: Option<bool>,
+    pub is_in_debug_mode: Option<bool>,
+    pub routes: Vec<Route>,
+    pub header_rules: Vec<HeaderRule>,
+    pub redirects: Vec<Redirect>,
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+pub const REDIRECT_STATUS_CODES: [u16; 5] = [301, 302, 303, 307, 308];
+
+// serves a url path prefix from another directory, ex. "/docs" from "/srv/docs"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    pub path: String,
+    pub serve_path: PathBuf,
+}
+
+impl Route {
+    pub fn strip_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        strip_path_prefix(&self.path, path)
+    }
+}
+
+// additional response headers for everything below a url path prefix
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderRule {
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl HeaderRule {
+    pub fn matches(&self, path: &str) -> bool {
+        strip_path_prefix(&self.path, path).is_some()
+    }
+}
+
+// "from" matches exactly, unless it ends in "/*", then everything below is redirected
+// and appended to "to" if it ends in "/*" as well, the query of the request is kept unless "to" has one of its own
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redirect {
+    pub from: String,
+    pub to: String,
+    pub status: u16,
+}
+
+impl Redirect {
+    pub fn get_location(&self, path: &str, query: Option<&str>) -> Option<String> {
+        let location = match self.from.strip_suffix("/*") {
+            Some(prefix) => {
+                let remainder = strip_path_prefix(prefix, path)?;
+                match self.to.strip_suffix("/*") {
+                    Some(to) => format!("{}{}", to, remainder),
+                    None => self.to.clone(),
+                }
+            }
+            None if self.from == path => self.to.clone(),
+            None => return None,
+        };
+
+        match query.filter(|query| !query.is_empty()) {
+            Some(query) if !self.to.contains('?') => {
+                // goes in front of a fragment, which always comes last
+                let fragment_start = location.find('#').unwrap_or(location.len());
+                Some(format!("{}?{}{}", &location[..fragment_start], query, &location[fragment_start..]))
+            }
+            _ => Some(location),
+        }
+    }
+}
+
+// prefixes only match whole path segments, so "/doc" doesn't match "/docs"
+fn strip_path_prefix<'a>(prefix: &str, path: &'a str) -> Option<&'a str> {
+    let remainder = path.strip_prefix(prefix.trim_end_matches('/'))?;
+    if remainder.is_empty() || remainder.starts_with('/') {
+        Some(remainder)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{HeaderRule, Redirect, Route};
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        let route = Route { path: String::from("/docs/"), serve_path: PathBuf::from("/srv/docs") };
+        assert_eq!(route.strip_path("/docs/guide.html"), Some("/guide.html"));
+        assert_eq!(route.strip_path("/docs"), Some(""));
+        assert_eq!(route.strip_path("/docsearch"), None);
+
+        let rule = HeaderRule { path: String::from("/"), headers: vec![] };
+        assert!(rule.matches("/anything"));
+    }
+
+    #[test]
+    fn redirects_exact_and_wildcard_paths() {
+        let exact = Redirect { from: String::from("/old"), to: String::from("/new"), status: 301 };
+        assert_eq!(exact.get_location("/old", None), Some(String::from("/new")));
+        assert_eq!(exact.get_location("/old/page", None), None);
+
+        let wildcard = Redirect { from: String::from("/blog/*"), to: String::from("https://blog.example.com/*"), status: 308 };
+        assert_eq!(wildcard.get_location("/blog/2021/post", None), Some(String::from("https://blog.example.com/2021/post")));
+        assert_eq!(wildcard.get_location("/blogroll", None), None);
+    }
+
+    #[test]
+    fn redirects_keep_the_query() {
+        let wildcard = Redirect { from: String::from("/blog/*"), to: String::from("/posts/*"), status: 301 };
+        assert_eq!(wildcard.get_location("/blog/post", Some("page=2")), Some(String::from("/posts/post?page=2")));
+        assert_eq!(wildcard.get_location("/blog/post", Some("")), Some(String::from("/posts/post")));
+
+        let with_fragment = Redirect { from: String::from("/old"), to: String::from("/new#top"), status: 301 };
+        assert_eq!(with_fragment.get_location("/old", Some("page=2")), Some(String::from("/new?page=2#top")));
+
+        // the query of the rule wins
+        let with_query = Redirect { from: String::from("/old"), to: String::from("/new?from=old"), status: 301 };
+        assert_eq!(with_query.get_location("/old", Some("page=2")), Some(String::from("/new?from=old")));
+    }
+}
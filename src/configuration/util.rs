@@ -1,20 +1,68 @@
 use std::str::FromStr;
 
+use super::error::ConfigError;
+
 pub fn check_for_missing_next_value(args: &[String], index: usize) -> bool {
     args.len() <= index + 1 || args[index + 1].starts_with("--")
 }
 
-pub fn parse_next_arg<T>(args: &[String], index: usize) -> Result<T, String>
+pub fn get_next_value(args: &[String], index: usize) -> Result<&str, ConfigError> {
+    if check_for_missing_next_value(args, index) {
+        return Err(ConfigError::MissingValue { flag: args[index].to_string() });
+    }
+
+    Ok(&args[index + 1])
+}
+
+pub fn parse_next_arg<T>(args: &[String], index: usize) -> Result<T, ConfigError>
     where
         T: FromStr,
 {
-    let next_arg = args[index + 1].to_string();
+    let next_arg = get_next_value(args, index)?;
 
     match next_arg.parse::<T>() {
         Ok(num) => Ok(num),
-        Err(_) => return Err(String::from("Invalid port number")),
+        Err(_) => Err(ConfigError::InvalidValue {
+            flag: args[index].to_string(),
+            value: next_arg.to_string(),
+        }),
+    }
+
+}
+
+// mappings are given as "extension=type", ex. "wasm=application/wasm"
+pub fn parse_mime_type_mapping(mapping: &str) -> Result<(String, String), String> {
+    match mapping.split_once('=') {
+        Some((extension, mime_type)) if !extension.trim().is_empty() && !mime_type.trim().is_empty() => {
+            let extension = extension.trim().trim_start_matches('.').to_ascii_lowercase();
+            Ok((extension, String::from(mime_type.trim())))
+        }
+        _ => Err(format!("Invalid mime type mapping: \"{}\"", mapping)),
     }
+}
+
+// permissions are given in octal like for chmod, ex. "660"
+pub fn parse_file_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode.trim().trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+}
 
+// field names are tokens, values must not be able to break out of their header line (rfc7230 section 3.2)
+pub fn is_valid_header(field_name: &str, field_value: &str) -> bool {
+    const TOKEN_SYMBOLS: &str = "!#$%&'*+-.^_`|~";
+
+    !field_name.is_empty()
+        && field_name.chars().all(|character| character.is_ascii_alphanumeric() || TOKEN_SYMBOLS.contains(character))
+        && field_value.chars().all(|character| character == '\t' || !character.is_control())
+}
+
+// the config file is loaded before the other arguments, so they can override its values
+pub fn find_config_file_path(args: &[String]) -> Option<&str> {
+    args.iter()
+        .position(|arg| arg == "--config")
+        .filter(|index| !check_for_missing_next_value(args, *index))
+        .map(|index| args[index + 1].as_str())
 }
 
 #[cfg(test)]
@@ -23,14 +71,54 @@ mod tests {
     fn missing_value_check_catches_missing_value() {
         let args = [String::from("--port"), String::from("--wrong-val")];
         let index = 0;
-        assert_eq!(true, super::check_for_missing_next_value(&args, index));
+        assert!(super::check_for_missing_next_value(&args, index));
     }
 
     #[test]
     fn missing_value_check_doesnt_catch_correct_values() {
         let args = [String::from("--port"), String::from("8080")];
         let index = 0;
-        assert_eq!(false, super::check_for_missing_next_value(&args, index));
+        assert!(!super::check_for_missing_next_value(&args, index));
     }
-}
 
+    #[test]
+    fn parse_errors_name_the_flag_and_value() {
+        let args = [String::from("--timeout"), String::from("soon")];
+        assert_eq!(super::parse_next_arg::<u64>(&args, 0), Err(super::ConfigError::InvalidValue {
+            flag: String::from("--timeout"),
+            value: String::from("soon"),
+        }));
+
+        let args = [String::from("--port")];
+        assert_eq!(super::parse_next_arg::<u16>(&args, 0), Err(super::ConfigError::MissingValue {
+            flag: String::from("--port"),
+        }));
+    }
+
+    #[test]
+    fn mime_type_mapping_is_normalized() {
+        let result = super::parse_mime_type_mapping(".WASM=application/wasm");
+        assert_eq!(result, Ok((String::from("wasm"), String::from("application/wasm"))));
+    }
+
+    #[test]
+    fn invalid_mime_type_mapping_is_rejected() {
+        assert!(super::parse_mime_type_mapping("wasm").is_err());
+        assert!(super::parse_mime_type_mapping("=application/wasm").is_err());
+    }
+
+    #[test]
+    fn file_modes_are_octal() {
+        assert_eq!(super::parse_file_mode("660"), Some(0o660));
+        assert_eq!(super::parse_file_mode("0o600"), Some(0o600));
+        assert_eq!(super::parse_file_mode("999"), None);
+        assert_eq!(super::parse_file_mode("1777"), None);
+    }
+
+    #[test]
+    fn header_injection_is_rejected() {
+        assert!(super::is_valid_header("Cache-Control", "max-age=60"));
+        assert!(!super::is_valid_header("Cache Control", "max-age=60"));
+        assert!(!super::is_valid_header("X-Test", "a\r\nSet-Cookie: b"));
+    }
+}
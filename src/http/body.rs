@@ -0,0 +1,265 @@
+use memchr::memchr;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use super::parser::{self, MAX_HEADER_SECTION_SIZE};
+use super::request::{HttpHeader, HttpParsingError};
+
+// chunk extensions aren't used, but still have to fit into a chunk line
+const MAX_CHUNK_LINE_LENGTH: usize = 4 * 1024;
+
+// how the end of a request body is found (rfc9112 section 6.3)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BodyFraming {
+    None,
+    ContentLength(u64),
+    Chunked,
+}
+
+#[derive(Debug, PartialEq)]
+enum BodyState {
+    Remaining(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailers,
+    Done,
+}
+
+// reads a request body piece by piece right from the connection, so it never has to be kept in memory as a whole
+pub struct BodyReader<'a, R> {
+    reader: &'a mut R,
+    state: BodyState,
+    max_size: u64,
+    size: u64,
+    line: Vec<u8>,
+    trailer_size: usize,
+    trailers: Vec<HttpHeader>,
+}
+
+impl<'a, R> BodyReader<'a, R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    // bodies larger than max_size are answered with a 413, a too large Content-Length before anything is read
+    pub fn new(reader: &'a mut R, framing: BodyFraming, max_size: u64) -> BodyReader<'a, R> {
+        let state = match framing {
+            BodyFraming::None | BodyFraming::ContentLength(0) => BodyState::Done,
+            BodyFraming::ContentLength(length) => BodyState::Remaining(length),
+            BodyFraming::Chunked => BodyState::ChunkSize,
+        };
+
+        BodyReader {
+            reader,
+            state,
+            max_size,
+            size: 0,
+            line: vec![],
+            trailer_size: 0,
+            trailers: vec![],
+        }
+    }
+
+    // returns 0 once the body is complete
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<usize, HttpParsingError> {
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        let length = self.fill().await?.min(buffer.len());
+        if length == 0 {
+            return Ok(0);
+        }
+
+        // already buffered by fill, so this returns right away
+        let available = self.reader.fill_buf().await.map_err(|_| HttpParsingError::TcpIssue())?;
+        buffer[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+
+    pub async fn read_to_end(&mut self) -> Result<Vec<u8>, HttpParsingError> {
+        let mut body = vec![];
+        loop {
+            let length = self.fill().await?;
+            if length == 0 {
+                return Ok(body);
+            }
+            let available = self.reader.fill_buf().await.map_err(|_| HttpParsingError::TcpIssue())?;
+            body.extend_from_slice(&available[..length]);
+            self.consume(length);
+        }
+    }
+
+    // skips the rest of the body, so the next request on the connection can be read
+    pub async fn discard(&mut self) -> Result<u64, HttpParsingError> {
+        let mut discarded = 0;
+        loop {
+            let length = self.fill().await?;
+            if length == 0 {
+                return Ok(discarded);
+            }
+            self.consume(length);
+            discarded += length as u64;
+        }
+    }
+
+    // only filled in once a chunked body was read to its end
+    pub fn trailers(&self) -> &[HttpHeader] {
+        &self.trailers
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == BodyState::Done
+    }
+
+    // moves on to the next bit of data and returns how much of it is buffered, 0 at the end of the body
+    async fn fill(&mut self) -> Result<usize, HttpParsingError> {
+        loop {
+            let remaining = match self.state {
+                BodyState::Remaining(remaining) | BodyState::ChunkData(remaining) => remaining,
+                BodyState::ChunkSize => {
+                    self.read_line(MAX_CHUNK_LINE_LENGTH, HttpParsingError::InvalidChunkFormat).await?;
+                    self.state = match parser::parse_chunk_size(&self.line)? {
+                        0 => BodyState::Trailers,
+                        chunk_size => BodyState::ChunkData(chunk_size),
+                    };
+                    continue;
+                }
+                BodyState::ChunkEnd => {
+                    self.read_line(MAX_CHUNK_LINE_LENGTH, HttpParsingError::InvalidChunkFormat).await?;
+                    if !self.line.is_empty() {
+                        return Err(HttpParsingError::InvalidChunkFormat);
+                    }
+                    self.state = BodyState::ChunkSize;
+                    continue;
+                }
+                BodyState::Trailers => {
+                    let max_length = MAX_HEADER_SECTION_SIZE - self.trailer_size;
+                    self.read_line(max_length, HttpParsingError::HeadersTooLarge).await?;
+                    if self.line.is_empty() {
+                        self.state = BodyState::Done;
+                        continue;
+                    }
+                    self.trailer_size += self.line.len();
+                    let field = parser::parse_field_line(&self.line)?;
+                    self.trailers.push(HttpHeader {
+                        field_name: String::from(field.name),
+                        field_value: String::from(field.value),
+                    });
+                    continue;
+                }
+                BodyState::Done => return Ok(0),
+            };
+
+            // checked before reading, so oversized bodies are turned away without receiving them
+            if self.size.saturating_add(remaining) > self.max_size {
+                return Err(HttpParsingError::BodyTooLarge);
+            }
+
+            let available = self.reader.fill_buf().await.map_err(|_| HttpParsingError::TcpIssue())?;
+            // the connection was closed before the body was complete
+            if available.is_empty() {
+                return Err(HttpParsingError::TcpIssue());
+            }
+            return Ok((available.len() as u64).min(remaining) as usize);
+        }
+    }
+
+    fn consume(&mut self, length: usize) {
+        self.reader.consume(length);
+        self.size += length as u64;
+        self.state = match self.state {
+            BodyState::Remaining(remaining) if remaining == length as u64 => BodyState::Done,
+            BodyState::Remaining(remaining) => BodyState::Remaining(remaining - length as u64),
+            BodyState::ChunkData(remaining) if remaining == length as u64 => BodyState::ChunkEnd,
+            BodyState::ChunkData(remaining) => BodyState::ChunkData(remaining - length as u64),
+            _ => unreachable!("only data is consumed"),
+        };
+    }
+
+    // reads a line into self.line without its line ending
+    async fn read_line(&mut self, max_length: usize, too_long_error: HttpParsingError) -> Result<(), HttpParsingError> {
+        self.line.clear();
+        loop {
+            let available = self.reader.fill_buf().await.map_err(|_| HttpParsingError::TcpIssue())?;
+            if available.is_empty() {
+                return Err(HttpParsingError::TcpIssue());
+            }
+            let (length, is_line_complete) = match memchr(b'\n', available) {
+                Some(line_end) => (line_end + 1, true),
+                None => (available.len(), false),
+            };
+            if self.line.len() + length > max_length {
+                return Err(too_long_error);
+            }
+            self.line.extend_from_slice(&available[..length]);
+            self.reader.consume(length);
+
+            if is_line_complete {
+                let line_length = parser::trim_line_end(&self.line).len();
+                self.line.truncate(line_length);
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::request::HttpParsingError;
+
+    use super::{BodyFraming, BodyReader};
+
+    #[tokio::test]
+    async fn reads_body_with_content_length() {
+        let mut raw_body: &[u8] = b"hello worldGET / HTTP/1.1\r\n";
+        let mut body = BodyReader::new(&mut raw_body, BodyFraming::ContentLength(11), 1024);
+
+        let mut buffer = [0; 5];
+        assert_eq!(body.read(&mut buffer).await, Ok(5));
+        assert_eq!(&buffer, b"hello");
+        assert_eq!(body.read_to_end().await, Ok(b" world".to_vec()));
+        assert!(body.is_complete());
+        assert_eq!(raw_body, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test]
+    async fn decodes_chunked_body_with_trailers() {
+        let mut raw_body: &[u8] = b"5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nChecksum: abc\r\n\r\nGET / HTTP/1.1\r\n";
+        let mut body = BodyReader::new(&mut raw_body, BodyFraming::Chunked, 1024);
+
+        assert_eq!(body.read_to_end().await, Ok(b"hello world".to_vec()));
+        assert_eq!(body.trailers()[0].field_name, "Checksum");
+        assert_eq!(body.trailers()[0].field_value, "abc");
+        assert_eq!(raw_body, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[tokio::test]
+    async fn enforces_maximum_body_size() {
+        let mut raw_body: &[u8] = b"";
+        let mut body = BodyReader::new(&mut raw_body, BodyFraming::ContentLength(2048), 1024);
+        assert_eq!(body.discard().await, Err(HttpParsingError::BodyTooLarge));
+
+        let mut raw_body: &[u8] = b"4\r\nabcd\r\n4\r\nefgh\r\n0\r\n\r\n";
+        let mut body = BodyReader::new(&mut raw_body, BodyFraming::Chunked, 6);
+        assert_eq!(body.discard().await, Err(HttpParsingError::BodyTooLarge));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_chunks() {
+        let invalid_bodies: [&[u8]; 4] = [
+            b"x\r\nhello\r\n0\r\n\r\n",
+            b"5\r\nhello world\r\n0\r\n\r\n",
+            b"11111111111111111\r\n",
+            b"5 garbage\r\nhello\r\n0\r\n\r\n",
+        ];
+
+        for mut raw_body in invalid_bodies.iter().copied() {
+            let mut body = BodyReader::new(&mut raw_body, BodyFraming::Chunked, u64::MAX);
+            assert_eq!(body.discard().await, Err(HttpParsingError::InvalidChunkFormat));
+        }
+
+        let mut raw_body: &[u8] = b"5\r\nhel";
+        let mut body = BodyReader::new(&mut raw_body, BodyFraming::Chunked, u64::MAX);
+        assert_eq!(body.discard().await, Err(HttpParsingError::TcpIssue()));
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::Error;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::request::HttpHeader;
+
+// small enough to get the first bytes out early, large enough that the chunk lines don't matter
+const CHUNK_SIZE: usize = 16 * 1024;
+
+// chunked transfer coding as described in rfc9112 section 7.1, for content whose length isn't known up front
+pub async fn write_chunks<R, W>(content: &mut R, stream: &mut W) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin,
+{
+    // room for the chunk size in front of the data and the line ending after it
+    const SIZE_LINE_LENGTH: usize = 8;
+    let mut buffer = vec![0; SIZE_LINE_LENGTH + CHUNK_SIZE + 2];
+
+    loop {
+        let len = content.read(&mut buffer[SIZE_LINE_LENGTH..SIZE_LINE_LENGTH + CHUNK_SIZE]).await?;
+        // an empty chunk would end the content
+        if len == 0 {
+            return Ok(());
+        }
+
+        // a chunk goes out in a single write, so it doesn't end up in three packets
+        let size_line = format!("{:x}\r\n", len);
+        let start = SIZE_LINE_LENGTH - size_line.len();
+        buffer[start..SIZE_LINE_LENGTH].copy_from_slice(size_line.as_bytes());
+        buffer[SIZE_LINE_LENGTH + len..SIZE_LINE_LENGTH + len + 2].copy_from_slice(b"\r\n");
+        stream.write_all(&buffer[start..SIZE_LINE_LENGTH + len + 2]).await?;
+    }
+}
+
+// the last chunk is empty and followed by the trailer section
+pub async fn write_last_chunk<W>(trailers: &[HttpHeader], stream: &mut W) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut last_chunk = String::from("0\r\n");
+    for trailer in trailers {
+        last_chunk.push_str(&trailer.to_string());
+    }
+    last_chunk.push_str("\r\n");
+    stream.write_all(last_chunk.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::http::request::HttpHeader;
+
+    #[tokio::test]
+    async fn encodes_content_in_chunks() {
+        let content = vec![b'a'; 20_000];
+        let trailer = HttpHeader {
+            field_name: String::from("Checksum"),
+            field_value: String::from("abc"),
+        };
+
+        let mut sent = vec![];
+        super::write_chunks(&mut content.as_slice(), &mut sent).await.unwrap();
+        super::write_last_chunk(&[trailer], &mut sent).await.unwrap();
+
+        let mut expected = b"4000\r\n".to_vec();
+        expected.extend_from_slice(&content[..16_384]);
+        expected.extend_from_slice(b"\r\ne20\r\n");
+        expected.extend_from_slice(&content[16_384..]);
+        expected.extend_from_slice(b"\r\n0\r\nChecksum: abc\r\n\r\n");
+        assert_eq!(sent, expected);
+    }
+}
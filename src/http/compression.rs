@@ -0,0 +1,335 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufReader, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::configuration::Config;
+
+use super::encoding::{self, ContentEncoding};
+use super::mime;
+
+// binary formats that aren't already compressed
+const COMPRESSIBLE_BINARY_TYPES: &[&str] = &[
+    "application/wasm",
+    "application/vnd.ms-fontobject",
+    "font/ttf",
+    "font/otf",
+    "image/bmp",
+    "image/vnd.microsoft.icon",
+];
+
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    mime::is_text_type(essence) || COMPRESSIBLE_BINARY_TYPES.contains(&essence)
+}
+
+// compressed variants are cached on disk, keyed by the original path and its modification time, the returned
+// file is already open so it can't be swapped out before it's served
+pub fn get_compressed_file(
+    identity_path: &Path,
+    accept_encoding: Option<&str>,
+    config: &Config,
+) -> Option<(File, ContentEncoding)> {
+    let encoding = encoding::negotiate_encodings(accept_encoding)
+        .into_iter()
+        .next()
+        .filter(|encoding| *encoding != ContentEncoding::Identity)?;
+
+    let meta_data = identity_path.metadata().ok()?;
+    let last_modified = meta_data.modified().ok()?;
+    let since_epoch = last_modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    let path_key = hash_key(&[identity_path.as_os_str().as_bytes()]);
+    let variant_key = hash_key(&[
+        &since_epoch.as_nanos().to_le_bytes(),
+        &meta_data.len().to_le_bytes(),
+        &config.compression_level.to_le_bytes(),
+    ]);
+    let file_name_prefix = format!("{:016x}-", path_key);
+    let cache_path = config.compression_cache_path.join(format!(
+        "{}{:016x}.{}",
+        file_name_prefix,
+        variant_key,
+        encoding.as_token(),
+    ));
+
+    if let Err(err) = prepare_cache_directory(&config.compression_cache_path) {
+        if config.is_in_debug_mode {
+            eprintln!("Compression Error: {}: {}", config.compression_cache_path.display(), err);
+        }
+        return None;
+    }
+
+    if let Some(cache_file) = open_cache_entry(&cache_path) {
+        return Some((cache_file, encoding));
+    }
+
+    if let Err(err) = write_cache_entry(identity_path, &cache_path, encoding, last_modified, config) {
+        if config.is_in_debug_mode {
+            eprintln!("Compression Error: {}", err);
+        }
+        return None;
+    }
+
+    // opened before cleaning up, so even a concurrent request for another variant can't take it away anymore
+    let cache_file = open_cache_entry(&cache_path);
+    remove_outdated_entries(&cache_path, &file_name_prefix, encoding);
+    cache_file.map(|cache_file| (cache_file, encoding))
+}
+
+// the cache directory is created private to the current user, an existing one is only used if no one else owns
+// or can write to it, otherwise others could place their own content under the predictable entry names
+fn prepare_cache_directory(cache_directory: &Path) -> io::Result<()> {
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(cache_directory)?;
+
+    let meta_data = fs::symlink_metadata(cache_directory)?;
+    if !meta_data.is_dir() || meta_data.uid() != current_user_id() || meta_data.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "cache directory has to be owned and only writable by the current user",
+        ));
+    }
+    Ok(())
+}
+
+// symlinks are never followed and only regular files of the current user are served
+fn open_cache_entry(cache_path: &Path) -> Option<File> {
+    let cache_file = File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(cache_path)
+        .ok()?;
+    let meta_data = cache_file.metadata().ok()?;
+    Some(cache_file).filter(|_| meta_data.is_file() && meta_data.uid() == current_user_id())
+}
+
+fn current_user_id() -> u32 {
+    // getuid can't fail
+    unsafe { libc::getuid() }
+}
+
+fn write_cache_entry(
+    identity_path: &Path,
+    cache_path: &Path,
+    encoding: ContentEncoding,
+    last_modified: SystemTime,
+    config: &Config,
+) -> io::Result<()> {
+    // write into a unique temporary file first, so concurrent requests never see partial content
+    let temporary_path = cache_path.with_extension(format!(
+        "{:016x}.tmp",
+        RandomState::new().build_hasher().finish()
+    ));
+
+    let result = compress(identity_path, &temporary_path, encoding, config.compression_level)
+        // keeps Last-Modified of the response in line with the original file
+        .and_then(|cache_file| cache_file.set_modified(last_modified))
+        .and_then(|_| fs::rename(&temporary_path, cache_path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+    }
+    result
+}
+
+// removes the other variants of the same file with the same encoding, the current one could have just been put in
+// place by a concurrent request
+fn remove_outdated_entries(cache_path: &Path, file_name_prefix: &str, encoding: ContentEncoding) {
+    let (cache_directory, current_file_name) = match (cache_path.parent(), cache_path.file_name()) {
+        (Some(cache_directory), Some(current_file_name)) => (cache_directory, current_file_name.to_string_lossy()),
+        _ => return,
+    };
+    let entries = match fs::read_dir(cache_directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let suffix = format!(".{}", encoding.as_token());
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if file_name.starts_with(file_name_prefix) && file_name.ends_with(&suffix) && file_name != current_file_name {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn compress(source_path: &Path, target_path: &Path, encoding: ContentEncoding, level: u32) -> io::Result<File> {
+    let mut source = BufReader::new(File::open(source_path)?);
+    let target = File::options().write(true).create_new(true).mode(0o600).open(target_path)?;
+
+    match encoding {
+        ContentEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(target, Compression::new(level.min(9)));
+            io::copy(&mut source, &mut encoder)?;
+            let target = encoder.finish()?;
+            target.sync_all()?;
+            Ok(target)
+        }
+        ContentEncoding::Brotli => {
+            const BUFFER_SIZE: usize = 4096;
+            const WINDOW_SIZE: u32 = 22;
+
+            let mut encoder = brotli::CompressorWriter::new(target, BUFFER_SIZE, level.min(11), WINDOW_SIZE);
+            io::copy(&mut source, &mut encoder)?;
+            encoder.flush()?;
+            let target = encoder.into_inner();
+            target.sync_all()?;
+            Ok(target)
+        }
+        ContentEncoding::Identity => Err(io::Error::new(io::ErrorKind::InvalidInput, "identity is no compression")),
+    }
+}
+
+// 64 bit FNV-1a, the names have to stay the same across builds so outdated entries are still found and removed,
+// which the standard library hashers don't promise
+fn hash_key(parts: &[&[u8]]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(OFFSET_BASIS, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    use std::sync::{Arc, Barrier};
+    use std::{env, fs, thread};
+
+    use flate2::read::GzDecoder;
+
+    use crate::configuration::Config;
+    use crate::http::encoding::ContentEncoding;
+    use crate::test_util::TempDirectory;
+
+    #[test]
+    fn detects_compressible_types() {
+        assert!(super::is_compressible("text/html; charset=utf-8"));
+        assert!(super::is_compressible("application/wasm"));
+        assert!(!super::is_compressible("image/png"));
+    }
+
+    #[test]
+    fn cache_keys_stay_the_same_across_builds() {
+        assert_eq!(super::hash_key(&[]), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(super::hash_key(&[b"a"]), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(super::hash_key(&[b"foo", b"bar"]), super::hash_key(&[b"foobar"]));
+    }
+
+    #[test]
+    fn caches_compressed_variant() {
+        let directory = TempDirectory::new("throwscape_compression_test");
+        let identity_path = directory.join("index.html");
+        let content = "<p>compress me</p>".repeat(100);
+        fs::write(&identity_path, &content).unwrap();
+
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.compression_cache_path = directory.join("cache");
+
+        let (cache_file, encoding) = super::get_compressed_file(&identity_path, Some("gzip"), &config).unwrap();
+        assert_eq!(encoding, ContentEncoding::Gzip);
+        assert!(cache_file.metadata().unwrap().len() < content.len() as u64);
+        assert_eq!(fs::metadata(&config.compression_cache_path).unwrap().permissions().mode() & 0o777, 0o700);
+
+        let mut decompressed = String::new();
+        GzDecoder::new(cache_file).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, content);
+
+        let cache_entries = || fs::read_dir(&config.compression_cache_path).unwrap().count();
+        assert_eq!(cache_entries(), 1);
+        assert!(super::get_compressed_file(&identity_path, Some("gzip"), &config).is_some());
+        assert_eq!(cache_entries(), 1);
+    }
+
+    #[test]
+    fn compresses_same_file_concurrently() {
+        let directory = TempDirectory::new("throwscape_compression_concurrent_test");
+        let identity_path = directory.join("index.html");
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.compression_cache_path = directory.join("cache");
+        let config = Arc::new(config);
+
+        // every round changes the file, so both requests write a new variant and clean up the previous one
+        for round in 1..=20 {
+            let content = "<p>compress me</p>".repeat(100 + round);
+            fs::write(&identity_path, &content).unwrap();
+
+            let barrier = Arc::new(Barrier::new(8));
+            let requests: Vec<_> = (0..8)
+                .map(|_| {
+                    let (identity_path, config, barrier) = (identity_path.clone(), Arc::clone(&config), Arc::clone(&barrier));
+                    thread::spawn(move || {
+                        barrier.wait();
+                        super::get_compressed_file(&identity_path, Some("gzip"), &config).map(|(cache_file, _)| {
+                            let mut decompressed = String::new();
+                            GzDecoder::new(cache_file).read_to_string(&mut decompressed).unwrap();
+                            decompressed
+                        })
+                    })
+                })
+                .collect();
+
+            for request in requests {
+                assert_eq!(request.join().unwrap().as_ref(), Some(&content));
+            }
+            assert_eq!(fs::read_dir(&config.compression_cache_path).unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    fn replaces_symlinked_cache_entries() {
+        let directory = TempDirectory::new("throwscape_compression_symlink_test");
+        let identity_path = directory.join("index.html");
+        let content = "<p>compress me</p>".repeat(100);
+        fs::write(&identity_path, &content).unwrap();
+        let secret_path = directory.join("secret");
+        fs::write(&secret_path, "secret").unwrap();
+
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.compression_cache_path = directory.join("cache");
+        super::get_compressed_file(&identity_path, Some("gzip"), &config).unwrap();
+
+        // someone swaps the entry for a link to another file
+        let cache_path = fs::read_dir(&config.compression_cache_path).unwrap().next().unwrap().unwrap().path();
+        fs::remove_file(&cache_path).unwrap();
+        symlink(&secret_path, &cache_path).unwrap();
+
+        let (cache_file, _) = super::get_compressed_file(&identity_path, Some("gzip"), &config).unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(cache_file).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, content);
+        assert!(!fs::symlink_metadata(&cache_path).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&secret_path).unwrap(), "secret");
+    }
+
+    #[test]
+    fn refuses_cache_directory_writable_by_others() {
+        let directory = TempDirectory::new("throwscape_compression_shared_test");
+        let identity_path = directory.join("index.html");
+        fs::write(&identity_path, "<p>compress me</p>".repeat(100)).unwrap();
+
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.compression_cache_path = directory.join("cache");
+        fs::create_dir(&config.compression_cache_path).unwrap();
+        fs::set_permissions(&config.compression_cache_path, fs::Permissions::from_mode(0o777)).unwrap();
+
+        assert!(super::get_compressed_file(&identity_path, Some("gzip"), &config).is_none());
+        assert_eq!(fs::read_dir(&config.compression_cache_path).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn skips_clients_preferring_identity() {
+        let config = Config::new(&[String::from("throwscape")]).unwrap();
+        assert!(super::get_compressed_file(&env::temp_dir(), None, &config).is_none());
+    }
+}
@@ -0,0 +1,259 @@
+use std::fmt;
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use httpdate::HttpDate;
+
+use super::request::HttpHeader;
+use super::{HttpMethod, HttpRequest};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct EntityTag {
+    pub is_weak: bool,
+    pub opaque_tag: String,
+}
+
+impl EntityTag {
+    // comparison functions as defined in rfc7232 section 2.3.2
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.is_weak && !other.is_weak && self.opaque_tag == other.opaque_tag
+    }
+
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.opaque_tag == other.opaque_tag
+    }
+
+    pub fn parse(entity_tag: &str) -> Option<EntityTag> {
+        let entity_tag = entity_tag.trim();
+        let (is_weak, quoted_tag) = match entity_tag.strip_prefix("W/") {
+            Some(quoted_tag) => (true, quoted_tag),
+            None => (false, entity_tag),
+        };
+
+        let opaque_tag = quoted_tag.strip_prefix('"')?.strip_suffix('"')?;
+        if opaque_tag.contains('"') {
+            return None;
+        }
+
+        Some(EntityTag {
+            is_weak,
+            opaque_tag: String::from(opaque_tag),
+        })
+    }
+}
+
+impl fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_weak {
+            write!(f, "W/\"{}\"", self.opaque_tag)
+        } else {
+            write!(f, "\"{}\"", self.opaque_tag)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Validators {
+    pub entity_tag: EntityTag,
+    pub last_modified: SystemTime,
+}
+
+impl Validators {
+    pub fn new(meta_data: &Metadata) -> Option<Validators> {
+        let last_modified = meta_data.modified().ok()?;
+        Some(Self::from_file_state(meta_data.len(), last_modified, SystemTime::now()))
+    }
+
+    fn from_file_state(len: u64, last_modified: SystemTime, now: SystemTime) -> Validators {
+        let since_epoch = last_modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        // a file changed within the last second could change again without a visible mtime difference
+        let is_weak = match now.duration_since(last_modified) {
+            Ok(age) => age < Duration::from_secs(1),
+            Err(_) => true,
+        };
+
+        Validators {
+            entity_tag: EntityTag {
+                is_weak,
+                opaque_tag: format!("{:x}-{:x}", since_epoch.as_nanos(), len),
+            },
+            last_modified,
+        }
+    }
+
+    pub fn generate_headers(&self) -> Vec<HttpHeader> {
+        vec![
+            HttpHeader {
+                field_name: String::from("ETag"),
+                field_value: self.entity_tag.to_string(),
+            },
+            HttpHeader {
+                field_name: String::from("Last-Modified"),
+                field_value: HttpDate::from(self.last_modified).to_string(),
+            },
+        ]
+    }
+
+    fn is_modified_since(&self, date: HttpDate) -> bool {
+        // http dates only have a precision of a second
+        HttpDate::from(self.last_modified) > date
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Precondition {
+    Passed,
+    NotModified,
+    Failed,
+}
+
+// evaluation order as described in rfc7232 section 6
+pub fn evaluate_preconditions(request: &HttpRequest, validators: &Validators) -> Precondition {
+    if let Some(if_match) = request.get_header("If-Match") {
+        if !matches_entity_tag_list(if_match, |entity_tag| entity_tag.strong_eq(&validators.entity_tag)) {
+            return Precondition::Failed;
+        }
+    } else if let Some(date) = parse_date_header(request, "If-Unmodified-Since") {
+        if validators.is_modified_since(date) {
+            return Precondition::Failed;
+        }
+    }
+
+    let is_safe_method = request.method == HttpMethod::GET || request.method == HttpMethod::HEAD;
+
+    if let Some(if_none_match) = request.get_header("If-None-Match") {
+        if matches_entity_tag_list(if_none_match, |entity_tag| entity_tag.weak_eq(&validators.entity_tag)) {
+            return if is_safe_method {
+                Precondition::NotModified
+            } else {
+                Precondition::Failed
+            };
+        }
+    } else if let Some(date) = parse_date_header(request, "If-Modified-Since") {
+        if is_safe_method && !validators.is_modified_since(date) {
+            return Precondition::NotModified;
+        }
+    }
+
+    Precondition::Passed
+}
+
+// invalid dates have to be ignored
+fn parse_date_header(request: &HttpRequest, field_name: &str) -> Option<HttpDate> {
+    request.get_header(field_name)?.parse::<HttpDate>().ok()
+}
+
+fn matches_entity_tag_list<F>(entity_tag_list: &str, matches: F) -> bool
+where
+    F: Fn(&EntityTag) -> bool,
+{
+    if entity_tag_list.trim() == "*" {
+        return true;
+    }
+
+    split_entity_tag_list(entity_tag_list)
+        .iter()
+        .filter_map(|entity_tag| EntityTag::parse(entity_tag))
+        .any(|entity_tag| matches(&entity_tag))
+}
+
+// opaque tags may contain commas, so only split outside of quotes
+fn split_entity_tag_list(entity_tag_list: &str) -> Vec<&str> {
+    let mut entity_tags = vec![];
+    let mut is_quoted = false;
+    let mut start = 0;
+
+    for (index, character) in entity_tag_list.char_indices() {
+        match character {
+            '"' => is_quoted = !is_quoted,
+            ',' if !is_quoted => {
+                entity_tags.push(&entity_tag_list[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    entity_tags.push(&entity_tag_list[start..]);
+    entity_tags
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::configuration::Config;
+    use crate::http::HttpRequest;
+
+    use super::{EntityTag, Precondition, Validators};
+
+    async fn request_with_header(method: &str, header: &str) -> HttpRequest {
+        let config = Config::new(&[String::from("throwscape")]).unwrap();
+        let raw_request = format!("{} / HTTP/1.1\r\n{}\r\n\r\n", method, header);
+        HttpRequest::new(&mut raw_request.as_bytes(), &config).await.unwrap()
+    }
+
+    #[test]
+    fn parses_weak_and_strong_entity_tags() {
+        assert_eq!(EntityTag::parse("\"abc\""), Some(EntityTag { is_weak: false, opaque_tag: String::from("abc") }));
+        assert_eq!(EntityTag::parse(" W/\"abc\""), Some(EntityTag { is_weak: true, opaque_tag: String::from("abc") }));
+        assert_eq!(EntityTag::parse("abc"), None);
+    }
+
+    #[test]
+    fn weak_comparison_ignores_weakness() {
+        let strong = EntityTag::parse("\"1\"").unwrap();
+        let weak = EntityTag::parse("W/\"1\"").unwrap();
+
+        assert!(strong.weak_eq(&weak));
+        assert!(!strong.strong_eq(&weak));
+        assert!(strong.strong_eq(&strong));
+    }
+
+    #[test]
+    fn splits_entity_tag_lists_outside_of_quotes() {
+        let result = super::split_entity_tag_list("\"a,b\", W/\"c\"");
+        assert_eq!(result, vec!["\"a,b\"", " W/\"c\""]);
+    }
+
+    #[test]
+    fn recently_modified_files_get_weak_entity_tags() {
+        let last_modified = UNIX_EPOCH + Duration::from_secs(1000);
+
+        let recent = Validators::from_file_state(10, last_modified, last_modified + Duration::from_millis(10));
+        let settled = Validators::from_file_state(10, last_modified, last_modified + Duration::from_secs(10));
+
+        assert!(recent.entity_tag.is_weak);
+        assert!(!settled.entity_tag.is_weak);
+        assert_eq!(recent.entity_tag.opaque_tag, settled.entity_tag.opaque_tag);
+    }
+
+    #[tokio::test]
+    async fn evaluates_preconditions_against_validators() {
+        let last_modified = UNIX_EPOCH + Duration::from_secs(784111777);
+        let validators = Validators::from_file_state(10, last_modified, last_modified + Duration::from_secs(10));
+        let entity_tag = validators.entity_tag.to_string();
+
+        let request = request_with_header("GET", &format!("If-None-Match: \"other\", {}", entity_tag)).await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::NotModified);
+
+        let request = request_with_header("GET", "If-Match: \"other\"").await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::Failed);
+
+        let request = request_with_header("GET", "If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT").await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::NotModified);
+
+        let request = request_with_header("GET", "If-Unmodified-Since: Sun, 06 Nov 1994 08:49:36 GMT").await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::Failed);
+
+        // HEAD is just as safe as GET
+        let request = request_with_header("HEAD", &format!("If-None-Match: {}", entity_tag)).await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::NotModified);
+
+        let request = request_with_header("HEAD", "If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT").await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::NotModified);
+
+        let request = request_with_header("HEAD", "If-Match: \"other\"").await;
+        assert_eq!(super::evaluate_preconditions(&request, &validators), Precondition::Failed);
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::{Path, PathBuf};
+
+use super::request::HttpHeader;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ContentEncoding {
+    Brotli,
+    Gzip,
+    Identity,
+}
+
+// ordered by preference in case the client weighs several encodings equally
+const SUPPORTED_ENCODINGS: [ContentEncoding; 3] = [
+    ContentEncoding::Brotli,
+    ContentEncoding::Gzip,
+    ContentEncoding::Identity,
+];
+
+impl ContentEncoding {
+    pub fn as_token(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gzip",
+            ContentEncoding::Identity => "identity",
+        }
+    }
+
+    fn file_extension(&self) -> Option<&'static str> {
+        match self {
+            ContentEncoding::Brotli => Some("br"),
+            ContentEncoding::Gzip => Some("gz"),
+            ContentEncoding::Identity => None,
+        }
+    }
+
+    fn matches_token(&self, token: &str) -> bool {
+        token.eq_ignore_ascii_case(self.as_token())
+            || (*self == ContentEncoding::Gzip && token.eq_ignore_ascii_case("x-gzip"))
+    }
+
+    pub fn generate_headers(&self) -> Vec<HttpHeader> {
+        match self {
+            ContentEncoding::Identity => vec![],
+            _ => vec![HttpHeader {
+                field_name: String::from("Content-Encoding"),
+                field_value: String::from(self.as_token()),
+            }],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EncodingSelection {
+    pub encoding: ContentEncoding,
+    // responses have to vary on Accept-Encoding as soon as there is more than one representation
+    pub has_variants: bool,
+}
+
+impl EncodingSelection {
+    pub fn generate_headers(&self) -> Vec<HttpHeader> {
+        let mut headers = self.encoding.generate_headers();
+        if self.has_variants {
+            headers.push(HttpHeader {
+                field_name: String::from("Vary"),
+                field_value: String::from("Accept-Encoding"),
+            });
+        }
+        headers
+    }
+}
+
+// returns the supported encodings acceptable to the client, best first (rfc7231 section 5.3.4)
+pub fn negotiate_encodings(accept_encoding: Option<&str>) -> Vec<ContentEncoding> {
+    let accept_encoding = match accept_encoding {
+        Some(accept_encoding) => accept_encoding,
+        // without the header any encoding is acceptable, but identity is the safe choice
+        None => return vec![ContentEncoding::Identity],
+    };
+
+    let codings: Vec<(&str, f32)> = accept_encoding
+        .split(',')
+        .filter_map(parse_coding)
+        .collect();
+
+    let mut acceptable: Vec<(ContentEncoding, f32)> = SUPPORTED_ENCODINGS
+        .iter()
+        .filter_map(|encoding| {
+            let explicit = codings.iter().find(|(token, _)| encoding.matches_token(token));
+            let wildcard = codings.iter().find(|(token, _)| *token == "*");
+            let quality = match (explicit, wildcard) {
+                (Some((_, quality)), _) | (None, Some((_, quality))) => *quality,
+                // identity is always acceptable unless excluded explicitly
+                (None, None) if *encoding == ContentEncoding::Identity => 0.001,
+                (None, None) => 0.0,
+            };
+            if quality > 0.0 {
+                Some((*encoding, quality))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // stable sort keeps the server preference for equal weights
+    acceptable.sort_by(|(_, first), (_, second)| second.partial_cmp(first).unwrap());
+    acceptable.into_iter().map(|(encoding, _)| encoding).collect()
+}
+
+fn parse_coding(coding: &str) -> Option<(&str, f32)> {
+    let mut parameters = coding.split(';');
+    let token = parameters.next()?.trim();
+    if token.is_empty() {
+        return None;
+    }
+
+    let mut quality = 1.0;
+    for parameter in parameters {
+        if let Some((name, value)) = parameter.split_once('=') {
+            if name.trim().eq_ignore_ascii_case("q") {
+                quality = value.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+            }
+        }
+    }
+    Some((token, quality))
+}
+
+// picks the best precompressed sibling (ex. "app.js.br" for "app.js") that exists within the serve path
+pub fn select_precompressed_file(
+    identity_path: &Path,
+    serve_path: &Path,
+    accept_encoding: Option<&str>,
+) -> (PathBuf, EncodingSelection) {
+    let available: Vec<(ContentEncoding, PathBuf)> = SUPPORTED_ENCODINGS
+        .iter()
+        .filter_map(|encoding| {
+            let extension = encoding.file_extension()?;
+            let mut sibling_path = identity_path.as_os_str().to_owned();
+            sibling_path.push(".");
+            sibling_path.push(extension);
+
+            let sibling_path = PathBuf::from(sibling_path).canonicalize().ok()?;
+            if sibling_path.starts_with(serve_path) && sibling_path.is_file() {
+                Some((*encoding, sibling_path))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let has_variants = !available.is_empty();
+
+    for encoding in negotiate_encodings(accept_encoding) {
+        if let Some((_, sibling_path)) = available.iter().find(|(available, _)| *available == encoding) {
+            return (sibling_path.clone(), EncodingSelection { encoding, has_variants });
+        }
+    }
+
+    // falling back to identity even if the client excluded it, as most servers do
+    (
+        identity_path.to_path_buf(),
+        EncodingSelection {
+            encoding: ContentEncoding::Identity,
+            has_variants,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::test_util::TempDirectory;
+
+    use super::{ContentEncoding, EncodingSelection};
+
+    #[test]
+    fn prefers_brotli_for_equal_weights() {
+        let result = super::negotiate_encodings(Some("gzip, deflate, br"));
+        assert_eq!(result, vec![ContentEncoding::Brotli, ContentEncoding::Gzip, ContentEncoding::Identity]);
+    }
+
+    #[test]
+    fn respects_quality_values() {
+        let result = super::negotiate_encodings(Some("br;q=0.5, gzip;q=0.8, identity;q=0"));
+        assert_eq!(result, vec![ContentEncoding::Gzip, ContentEncoding::Brotli]);
+    }
+
+    #[test]
+    fn wildcard_covers_unlisted_encodings() {
+        let result = super::negotiate_encodings(Some("gzip;q=0, *;q=0.3"));
+        assert_eq!(result, vec![ContentEncoding::Brotli, ContentEncoding::Identity]);
+    }
+
+    #[test]
+    fn missing_header_only_allows_identity() {
+        assert_eq!(super::negotiate_encodings(None), vec![ContentEncoding::Identity]);
+    }
+
+    #[test]
+    fn selects_existing_precompressed_sibling() {
+        let directory = TempDirectory::new("throwscape_precompressed_test");
+        let serve_path = directory.path().canonicalize().unwrap();
+        let identity_path = serve_path.join("app.js");
+        fs::write(&identity_path, "identity").unwrap();
+        fs::write(serve_path.join("app.js.gz"), "gzip").unwrap();
+
+        let (path, selection) = super::select_precompressed_file(&identity_path, &serve_path, Some("br, gzip"));
+        assert_eq!(path, serve_path.join("app.js.gz"));
+        assert_eq!(selection, EncodingSelection { encoding: ContentEncoding::Gzip, has_variants: true });
+
+        let (path, selection) = super::select_precompressed_file(&identity_path, &serve_path, None);
+        assert_eq!(path, identity_path);
+        assert_eq!(selection, EncodingSelection { encoding: ContentEncoding::Identity, has_variants: true });
+    }
+}
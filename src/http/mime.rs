@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use crate::configuration::Config;
+
+pub const PLAIN_TEXT: &str = "text/plain; charset=utf-8";
+
+// extension to media type mapping for the most common static web content
+const BUILT_IN_MIME_TYPES: &[(&str, &str)] = &[
+    // documents
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("xhtml", "application/xhtml+xml"),
+    ("css", "text/css"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("xml", "application/xml"),
+    ("rss", "application/rss+xml"),
+    ("atom", "application/atom+xml"),
+    ("pdf", "application/pdf"),
+    // scripts and data
+    ("js", "text/javascript"),
+    ("mjs", "text/javascript"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("wasm", "application/wasm"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    // images
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    // fonts
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    // audio and video
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("aac", "audio/aac"),
+    ("m4a", "audio/mp4"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("vtt", "text/vtt"),
+    // archives
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+];
+
+pub fn get_content_type(path: &Path, config: &Config) -> String {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    let mime_type = match extension {
+        Some(extension) => config
+            .mime_types
+            .get(&extension)
+            .map(String::as_str)
+            .or_else(|| lookup_built_in_mime_type(&extension))
+            .unwrap_or(&config.default_mime_type),
+        None => &config.default_mime_type,
+    };
+
+    add_charset(mime_type)
+}
+
+fn lookup_built_in_mime_type(extension: &str) -> Option<&'static str> {
+    BUILT_IN_MIME_TYPES
+        .iter()
+        .find(|(known_extension, _)| *known_extension == extension)
+        .map(|(_, mime_type)| *mime_type)
+}
+
+pub fn is_text_type(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or("").trim();
+    essence.starts_with("text/")
+        || essence.ends_with("+xml")
+        || essence.ends_with("+json")
+        || matches!(essence, "application/json" | "application/javascript" | "application/xml" | "application/yaml")
+}
+
+// files are assumed to be utf-8, types that already carry parameters are left as they are
+fn add_charset(mime_type: &str) -> String {
+    if is_text_type(mime_type) && !mime_type.contains(';') {
+        format!("{}; charset=utf-8", mime_type)
+    } else {
+        String::from(mime_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::configuration::Config;
+
+    fn config(args: &[&str]) -> Config {
+        let mut config_args = vec![String::from("throwscape")];
+        config_args.extend(args.iter().map(|arg| arg.to_string()));
+        Config::new(&config_args).unwrap()
+    }
+
+    #[test]
+    fn detects_built_in_types() {
+        let config = config(&[]);
+        assert_eq!(super::get_content_type(Path::new("/site/app.JS"), &config), "text/javascript; charset=utf-8");
+        assert_eq!(super::get_content_type(Path::new("/site/logo.png"), &config), "image/png");
+        assert_eq!(super::get_content_type(Path::new("/site/icon.svg"), &config), "image/svg+xml; charset=utf-8");
+    }
+
+    #[test]
+    fn falls_back_to_default_type() {
+        let config = config(&["--default-mime-type", "text/plain"]);
+        assert_eq!(super::get_content_type(Path::new("/site/LICENSE"), &config), "text/plain; charset=utf-8");
+        assert_eq!(super::get_content_type(Path::new("/site/data.unknown"), &config), "text/plain; charset=utf-8");
+    }
+
+    #[test]
+    fn configured_types_take_precedence() {
+        let config = config(&["--mime-type", ".js=application/javascript; charset=iso-8859-1"]);
+        assert_eq!(
+            super::get_content_type(Path::new("/site/app.js"), &config),
+            "application/javascript; charset=iso-8859-1"
+        );
+    }
+}
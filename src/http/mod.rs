@@ -1,6 +1,17 @@
+pub use self::body::{BodyFraming, BodyReader};
 pub use self::request::HttpRequest;
 pub use self::request::HttpMethod;
-pub use self::response::HttpResponse;
+pub use self::request::{HttpHeader, HttpParsingError, HttpRequestTarget, HttpVersion};
+pub use self::response::{ContentStream, HttpMessageContent, HttpResponse};
+pub use self::response::HttpStatus;
 
+mod body;
+mod chunked;
+mod compression;
+mod conditional;
+mod encoding;
+mod mime;
+mod parser;
+mod range;
 mod request;
 mod response;
@@ -0,0 +1,281 @@
+use std::str;
+
+use memchr::memchr;
+
+use super::request::{HttpParsingError, HttpVersion};
+
+// the head is only buffered up to these limits, so a client can't make the server buffer endlessly
+pub const MAX_REQUEST_LINE_LENGTH: usize = 8 * 1024;
+pub const MAX_HEADER_SECTION_SIZE: usize = 16 * 1024;
+
+// a request head as described in rfc9112 section 2.1, every part borrows from the buffer it was parsed from
+#[derive(Debug, PartialEq)]
+pub struct RequestHead<'a> {
+    pub method: &'a str,
+    pub target: &'a str,
+    pub version: HttpVersion,
+    pub fields: Vec<FieldLine<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FieldLine<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+// finds the end of a head in a buffer that keeps growing as more of it arrives, without scanning any byte twice
+#[derive(Default)]
+pub struct HeadScanner {
+    position: usize,
+    request_line_end: Option<usize>,
+}
+
+impl HeadScanner {
+    // returns the length of the head including the empty line that ends it, once it is complete
+    pub fn scan(&mut self, buffer: &[u8]) -> Result<Option<usize>, HttpParsingError> {
+        while let Some(offset) = memchr(b'\n', &buffer[self.position..]) {
+            let line_start = self.position;
+            self.position += offset + 1;
+            let is_empty_line = trim_line_end(&buffer[line_start..self.position]).is_empty();
+            self.check_limits(self.position)?;
+
+            match self.request_line_end {
+                // empty lines in front of the request line are ignored (rfc9112 section 2.2)
+                None if is_empty_line => {}
+                None => self.request_line_end = Some(self.position),
+                Some(_) if is_empty_line => return Ok(Some(self.position)),
+                Some(_) => {}
+            }
+        }
+
+        self.position = buffer.len();
+        self.check_limits(buffer.len())?;
+        Ok(None)
+    }
+
+    fn check_limits(&self, length: usize) -> Result<(), HttpParsingError> {
+        match self.request_line_end {
+            None if length > MAX_REQUEST_LINE_LENGTH => Err(HttpParsingError::TargetTooLong),
+            Some(request_line_end) if length - request_line_end > MAX_HEADER_SECTION_SIZE => {
+                Err(HttpParsingError::HeadersTooLarge)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// expects a complete head, as found by the HeadScanner
+pub fn parse_head(head: &[u8]) -> Result<RequestHead<'_>, HttpParsingError> {
+    let mut lines = Lines { remaining: head }.skip_while(|line| line.is_empty());
+
+    let request_line = lines.next().ok_or(HttpParsingError::InvalidSyntax)?;
+    let (method, target, version) = parse_request_line(request_line)?;
+
+    let mut fields = Vec::with_capacity(16);
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+        fields.push(parse_field_line(line)?);
+    }
+
+    Ok(RequestHead { method, target, version, fields })
+}
+
+// request-line = method SP request-target SP HTTP-version (rfc9112 section 3), separated by exactly one space each
+pub fn parse_request_line(line: &[u8]) -> Result<(&str, &str, HttpVersion), HttpParsingError> {
+    let method_end = memchr(b' ', line).ok_or(HttpParsingError::InvalidTargetFormat)?;
+    let method = &line[..method_end];
+    let rest = &line[method_end + 1..];
+    let target_end = memchr(b' ', rest).ok_or(HttpParsingError::InvalidVersionFormat)?;
+    let target = &rest[..target_end];
+    let version = &rest[target_end + 1..];
+
+    if method.is_empty() || !method.iter().all(|byte| is_token_byte(*byte)) {
+        return Err(HttpParsingError::InvalidMethodFormat);
+    }
+    if target.is_empty() || !target.iter().all(|byte| is_target_byte(*byte)) {
+        return Err(HttpParsingError::InvalidTargetFormat);
+    }
+    let version = parse_version(version)?;
+
+    // only ASCII is left at this point
+    Ok((as_str(method)?, as_str(target)?, version))
+}
+
+// HTTP-version = "HTTP/" DIGIT "." DIGIT
+pub fn parse_version(version: &[u8]) -> Result<HttpVersion, HttpParsingError> {
+    match version {
+        [b'H', b'T', b'T', b'P', b'/', major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            // later versions aren't sent as text, they are negotiated on the connection instead
+            if *major != b'1' {
+                return Err(HttpParsingError::UnsupportedVersion);
+            }
+            Ok(HttpVersion { major: major - b'0', minor: minor - b'0' })
+        }
+        _ => Err(HttpParsingError::InvalidSyntax),
+    }
+}
+
+// field-line = field-name ":" OWS field-value OWS (rfc9112 section 5), whitespace in front of the colon
+// and obsolete line folding are rejected
+pub fn parse_field_line(line: &[u8]) -> Result<FieldLine<'_>, HttpParsingError> {
+    let colon = memchr(b':', line).ok_or(HttpParsingError::InvalidHeaderFormat)?;
+    let name = &line[..colon];
+    if name.is_empty() || !name.iter().all(|byte| is_token_byte(*byte)) {
+        return Err(HttpParsingError::InvalidHeaderFormat);
+    }
+
+    let value = trim_whitespace(&line[colon + 1..]);
+    if !value.iter().all(|byte| is_field_value_byte(*byte)) {
+        return Err(HttpParsingError::InvalidHeaderFormat);
+    }
+
+    Ok(FieldLine {
+        name: as_str(name)?,
+        value: str::from_utf8(value).map_err(|_| HttpParsingError::InvalidHeaderFormat)?,
+    })
+}
+
+// chunk = chunk-size [ chunk-ext ] CRLF (rfc9112 section 7.1), extensions have no meaning to us and are skipped
+pub fn parse_chunk_size(line: &[u8]) -> Result<u64, HttpParsingError> {
+    let size_end = line.iter().position(|byte| !byte.is_ascii_hexdigit()).unwrap_or(line.len());
+    // more than 16 digits don't fit into 64 bits
+    if size_end == 0 || size_end > 16 {
+        return Err(HttpParsingError::InvalidChunkFormat);
+    }
+
+    let extensions = trim_whitespace(&line[size_end..]);
+    let are_extensions_valid = (extensions.is_empty() || extensions[0] == b';')
+        && extensions.iter().all(|byte| is_field_value_byte(*byte));
+    if !are_extensions_valid {
+        return Err(HttpParsingError::InvalidChunkFormat);
+    }
+
+    u64::from_str_radix(as_str(&line[..size_end])?, 16).map_err(|_| HttpParsingError::InvalidChunkFormat)
+}
+
+fn as_str(ascii: &[u8]) -> Result<&str, HttpParsingError> {
+    str::from_utf8(ascii).map_err(|_| HttpParsingError::InvalidSyntax)
+}
+
+// lines end with CRLF, a bare LF is accepted as well (rfc9112 section 2.2)
+struct Lines<'a> {
+    remaining: &'a [u8],
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let line_end = memchr(b'\n', self.remaining)?;
+        let line = &self.remaining[..line_end + 1];
+        self.remaining = &self.remaining[line_end + 1..];
+        Some(trim_line_end(line))
+    }
+}
+
+pub fn trim_line_end(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn trim_whitespace(value: &[u8]) -> &[u8] {
+    let start = value.iter().position(|byte| *byte != b' ' && *byte != b'\t').unwrap_or(value.len());
+    let end = value.iter().rposition(|byte| *byte != b' ' && *byte != b'\t').map_or(start, |end| end + 1);
+    &value[start..end]
+}
+
+// lookup tables, so checking a byte is a single load
+const TOKEN_BYTES: [bool; 256] = byte_table(b"!#$%&'*+-.^_`|~", true);
+const FIELD_VALUE_BYTES: [bool; 256] = byte_table(b" \t", false);
+
+const fn byte_table(extra_bytes: &[u8], is_token: bool) -> [bool; 256] {
+    let mut table = [false; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        table[byte] = if is_token {
+            (byte as u8).is_ascii_alphanumeric()
+        } else {
+            // VCHAR and obs-text
+            byte >= 0x21 && byte != 0x7f
+        };
+        byte += 1;
+    }
+    let mut index = 0;
+    while index < extra_bytes.len() {
+        table[extra_bytes[index] as usize] = true;
+        index += 1;
+    }
+    table
+}
+
+// tchar as in rfc9110 section 5.6.2
+fn is_token_byte(byte: u8) -> bool {
+    TOKEN_BYTES[byte as usize]
+}
+
+// field-vchar, SP and HTAB, which leaves out CR, LF, NUL and the other control characters
+fn is_field_value_byte(byte: u8) -> bool {
+    FIELD_VALUE_BYTES[byte as usize]
+}
+
+// targets are URIs (rfc3986), which only consist of visible ASCII
+fn is_target_byte(byte: u8) -> bool {
+    byte.is_ascii_graphic()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldLine, HeadScanner, HttpParsingError, HttpVersion};
+
+    #[test]
+    fn scans_a_head_arriving_in_pieces() {
+        let request = b"\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET /next HTTP/1.1\r\n";
+        let mut scanner = HeadScanner::default();
+
+        assert_eq!(scanner.scan(&request[..10]), Ok(None));
+        assert_eq!(scanner.scan(&request[..20]), Ok(None));
+        assert_eq!(scanner.scan(&request[..]), Ok(Some(37)));
+    }
+
+    #[test]
+    fn scanner_enforces_limits() {
+        let request = format!("GET /{} HTTP/1.1\r\n", "a".repeat(10_000));
+        assert_eq!(HeadScanner::default().scan(request.as_bytes()), Err(HttpParsingError::TargetTooLong));
+
+        let request = format!("GET / HTTP/1.1\r\n{}", "Cookie: a=b\r\n".repeat(2_000));
+        assert_eq!(HeadScanner::default().scan(request.as_bytes()), Err(HttpParsingError::HeadersTooLarge));
+    }
+
+    #[test]
+    fn parses_head_without_copying() {
+        let head = super::parse_head(b"GET /index.html HTTP/1.0\nHost:  localhost \t\r\nAccept:\r\n\r\n").unwrap();
+
+        assert_eq!(head.method, "GET");
+        assert_eq!(head.target, "/index.html");
+        assert_eq!(head.version, HttpVersion { major: 1, minor: 0 });
+        assert_eq!(head.fields, vec![
+            FieldLine { name: "Host", value: "localhost" },
+            FieldLine { name: "Accept", value: "" },
+        ]);
+    }
+
+    #[test]
+    fn rejects_what_rfc_9112_forbids() {
+        let invalid_heads: [(&[u8], HttpParsingError); 8] = [
+            (b"GET  / HTTP/1.1\r\n\r\n", HttpParsingError::InvalidTargetFormat),
+            (b"GET / HTTP/1.1 \r\n\r\n", HttpParsingError::InvalidSyntax),
+            (b"GE(T / HTTP/1.1\r\n\r\n", HttpParsingError::InvalidMethodFormat),
+            (b"GET / HTTP/1.1\r\nHost : localhost\r\n\r\n", HttpParsingError::InvalidHeaderFormat),
+            (b"GET / HTTP/1.1\r\nHost: local\rhost\r\n\r\n", HttpParsingError::InvalidHeaderFormat),
+            (b"GET / HTTP/1.1\r\nHost: localhost\r\n  folded\r\n\r\n", HttpParsingError::InvalidHeaderFormat),
+            (b"GET / HTTP/1.1\r\nX-Null: \0\r\n\r\n", HttpParsingError::InvalidHeaderFormat),
+            (b"GET / HTTP/3.0\r\n\r\n", HttpParsingError::UnsupportedVersion),
+        ];
+
+        for (head, error) in invalid_heads.iter() {
+            assert_eq!(super::parse_head(head).err().as_ref(), Some(error), "{}", String::from_utf8_lossy(head));
+        }
+    }
+}
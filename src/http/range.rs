@@ -0,0 +1,319 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{Error, SeekFrom};
+
+use httpdate::HttpDate;
+use tokio::fs::File;
+use tokio::io::{self, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use super::conditional::{EntityTag, Validators};
+use super::request::HttpHeader;
+
+// upper bound for ranges in a single request, larger sets are answered with the full file
+const MAX_RANGE_COUNT: usize = 32;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ByteRange {
+    pub start: u64,
+    // inclusive, as in rfc7233
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    fn to_content_range(self, complete_length: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, complete_length)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RangeSelection {
+    Full,
+    Partial(Vec<ByteRange>),
+    NotSatisfiable,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PartialContent {
+    ranges: Vec<ByteRange>,
+    complete_length: u64,
+    content_type: String,
+    boundary: String,
+}
+
+impl PartialContent {
+    pub fn new(ranges: Vec<ByteRange>, complete_length: u64, content_type: String) -> PartialContent {
+        PartialContent {
+            ranges,
+            complete_length,
+            content_type,
+            boundary: generate_boundary(),
+        }
+    }
+
+    fn is_multipart(&self) -> bool {
+        self.ranges.len() > 1
+    }
+
+    pub fn content_length(&self) -> u64 {
+        if !self.is_multipart() {
+            return self.ranges[0].len();
+        }
+
+        let parts_len: u64 = self.ranges.iter()
+            .map(|range| self.generate_part_header(*range).len() as u64 + range.len())
+            .sum();
+        parts_len + self.generate_closing_delimiter().len() as u64
+    }
+
+    pub fn generate_headers(&self) -> Vec<HttpHeader> {
+        if self.is_multipart() {
+            vec![HttpHeader {
+                field_name: String::from("Content-Type"),
+                field_value: format!("multipart/byteranges; boundary={}", self.boundary),
+            }]
+        } else {
+            vec![
+                HttpHeader {
+                    field_name: String::from("Content-Type"),
+                    field_value: self.content_type.clone(),
+                },
+                HttpHeader {
+                    field_name: String::from("Content-Range"),
+                    field_value: self.ranges[0].to_content_range(self.complete_length),
+                },
+            ]
+        }
+    }
+
+    pub async fn send<W>(&self, file: &mut File, stream: &mut W) -> Result<(), Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        for range in self.ranges.iter() {
+            if self.is_multipart() {
+                stream.write_all(self.generate_part_header(*range).as_bytes()).await?;
+            }
+            file.seek(SeekFrom::Start(range.start)).await?;
+            io::copy(&mut (&mut *file).take(range.len()), stream).await?;
+        }
+        if self.is_multipart() {
+            stream.write_all(self.generate_closing_delimiter().as_bytes()).await?;
+        }
+        Ok(())
+    }
+
+    fn generate_part_header(&self, range: ByteRange) -> String {
+        format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            self.boundary,
+            self.content_type,
+            range.to_content_range(self.complete_length),
+        )
+    }
+
+    fn generate_closing_delimiter(&self) -> String {
+        format!("\r\n--{}--\r\n", self.boundary)
+    }
+}
+
+fn generate_boundary() -> String {
+    // RandomState is seeded randomly, which is plenty to avoid collisions with the file content
+    let random = RandomState::new().build_hasher().finish();
+    format!("THROWSCAPE_{:016x}", random)
+}
+
+// syntactically invalid range headers are ignored as required by rfc7233 section 3.1
+pub fn parse_range_header(range_header: &str, complete_length: u64) -> RangeSelection {
+    let range_set = match range_header.trim().split_once('=') {
+        Some((unit, range_set)) if unit.trim().eq_ignore_ascii_case("bytes") => range_set,
+        _ => return RangeSelection::Full,
+    };
+
+    let mut ranges = vec![];
+    for range_spec in range_set.split(',') {
+        let range_spec = range_spec.trim();
+        if range_spec.is_empty() {
+            continue;
+        }
+        match parse_range_spec(range_spec, complete_length) {
+            Ok(Some(range)) => ranges.push(range),
+            Ok(None) => {}
+            Err(_) => return RangeSelection::Full,
+        }
+    }
+
+    if ranges.len() > MAX_RANGE_COUNT {
+        return RangeSelection::Full;
+    }
+    if ranges.is_empty() {
+        return RangeSelection::NotSatisfiable;
+    }
+
+    RangeSelection::Partial(coalesce_ranges(ranges))
+}
+
+// Ok(None) marks a valid but unsatisfiable range
+fn parse_range_spec(range_spec: &str, complete_length: u64) -> Result<Option<ByteRange>, ()> {
+    let (first, last) = range_spec.split_once('-').ok_or(())?;
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        let suffix_length = parse_position(last)?;
+        if suffix_length == 0 || complete_length == 0 {
+            return Ok(None);
+        }
+        let start = complete_length.saturating_sub(suffix_length);
+        return Ok(Some(ByteRange { start, end: complete_length - 1 }));
+    }
+
+    let start = parse_position(first)?;
+    let end = if last.is_empty() {
+        u64::MAX
+    } else {
+        parse_position(last)?
+    };
+
+    if end < start {
+        return Err(());
+    }
+    if start >= complete_length {
+        return Ok(None);
+    }
+    Ok(Some(ByteRange { start, end: end.min(complete_length - 1) }))
+}
+
+fn parse_position(position: &str) -> Result<u64, ()> {
+    if position.is_empty() || !position.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(());
+    }
+    position.parse::<u64>().map_err(|_| ())
+}
+
+fn coalesce_ranges(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut coalesced: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(previous) if range.start <= previous.end.saturating_add(1) => {
+                previous.end = previous.end.max(range.end);
+            }
+            _ => coalesced.push(range),
+        }
+    }
+    coalesced
+}
+
+// a range request is only honored if the validator in If-Range still matches the file
+pub fn is_if_range_fulfilled(if_range: Option<&str>, validators: Option<&Validators>) -> bool {
+    let if_range = match if_range {
+        Some(if_range) => if_range.trim(),
+        None => return true,
+    };
+    let validators = match validators {
+        Some(validators) => validators,
+        None => return false,
+    };
+
+    // If-Range requires a strong match, weak entity tags never fulfill it
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return match EntityTag::parse(if_range) {
+            Some(entity_tag) => entity_tag.strong_eq(&validators.entity_tag),
+            None => false,
+        };
+    }
+
+    match if_range.parse::<HttpDate>() {
+        Ok(date) => date == HttpDate::from(validators.last_modified),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::http::conditional::{EntityTag, Validators};
+
+    use super::{ByteRange, PartialContent, RangeSelection};
+
+    fn validators(last_modified: SystemTime) -> Validators {
+        Validators {
+            entity_tag: EntityTag { is_weak: false, opaque_tag: String::from("tag") },
+            last_modified,
+        }
+    }
+
+    #[test]
+    fn parses_single_range() {
+        let result = super::parse_range_header("bytes=0-99", 1000);
+        assert_eq!(result, RangeSelection::Partial(vec![ByteRange { start: 0, end: 99 }]));
+    }
+
+    #[test]
+    fn parses_open_and_suffix_ranges() {
+        let result = super::parse_range_header("bytes=900-", 1000);
+        assert_eq!(result, RangeSelection::Partial(vec![ByteRange { start: 900, end: 999 }]));
+
+        let result = super::parse_range_header("bytes=-2000", 1000);
+        assert_eq!(result, RangeSelection::Partial(vec![ByteRange { start: 0, end: 999 }]));
+    }
+
+    #[test]
+    fn coalesces_overlapping_ranges() {
+        let result = super::parse_range_header("bytes=500-600, 0-10,550-700", 1000);
+        let expected = RangeSelection::Partial(vec![
+            ByteRange { start: 0, end: 10 },
+            ByteRange { start: 500, end: 700 },
+        ]);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn gives_not_satisfiable_for_ranges_outside_file() {
+        assert_eq!(super::parse_range_header("bytes=1000-", 1000), RangeSelection::NotSatisfiable);
+        assert_eq!(super::parse_range_header("bytes=-0", 1000), RangeSelection::NotSatisfiable);
+    }
+
+    #[test]
+    fn ignores_invalid_range_headers() {
+        assert_eq!(super::parse_range_header("lines=0-10", 1000), RangeSelection::Full);
+        assert_eq!(super::parse_range_header("bytes=10-5", 1000), RangeSelection::Full);
+        assert_eq!(super::parse_range_header("bytes=a-b", 1000), RangeSelection::Full);
+    }
+
+    #[test]
+    fn multipart_content_length_matches_generated_parts() {
+        let first = ByteRange { start: 0, end: 9 };
+        let second = ByteRange { start: 20, end: 29 };
+        let partial_content = PartialContent::new(vec![first, second], 100, String::from("text/plain"));
+
+        let expected = partial_content.generate_part_header(first).len() as u64 + 10
+            + partial_content.generate_part_header(second).len() as u64 + 10
+            + partial_content.generate_closing_delimiter().len() as u64;
+        assert_eq!(partial_content.content_length(), expected);
+    }
+
+    #[test]
+    fn if_range_only_matches_identical_date() {
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(784111777);
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+        assert!(super::is_if_range_fulfilled(None, Some(&validators(last_modified))));
+        assert!(super::is_if_range_fulfilled(Some(date), Some(&validators(last_modified))));
+        assert!(!super::is_if_range_fulfilled(Some(date), Some(&validators(last_modified + Duration::from_secs(1)))));
+    }
+
+    #[test]
+    fn if_range_requires_strong_entity_tag() {
+        let validators = validators(SystemTime::now());
+
+        assert!(super::is_if_range_fulfilled(Some("\"tag\""), Some(&validators)));
+        assert!(!super::is_if_range_fulfilled(Some("W/\"tag\""), Some(&validators)));
+        assert!(!super::is_if_range_fulfilled(Some("\"other\""), Some(&validators)));
+    }
+}
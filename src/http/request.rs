@@ -1,11 +1,12 @@
-use std::io::{BufReader, BufRead};
-use std::net::TcpStream;
-use regex::Regex;
-
-use lazy_static::lazy_static;
+use std::fmt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::time;
 
 use crate::configuration::Config;
 
+use super::body::BodyFraming;
+use super::parser::{self, HeadScanner, RequestHead};
+
 #[derive(Debug)]
 #[derive(PartialEq)]
 pub enum HttpMethod {
@@ -28,6 +29,14 @@ pub enum HttpParsingError {
     InvalidTargetFormat,
     InvalidVersionFormat,
     InvalidHeaderFormat,
+    TargetTooLong,
+    HeadersTooLarge,
+    UnsupportedVersion,
+    InvalidChunkFormat,
+    AmbiguousBodyLength,
+    UnsupportedTransferCoding,
+    BodyTooLarge,
+    Timeout,
     TcpIssue(),
     UnknownMethod,
     UnknownScheme,
@@ -35,6 +44,7 @@ pub enum HttpParsingError {
 
 #[derive(Debug)]
 #[derive(PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum HttpRequestTarget {
     // Note: query as defined in RFC3986 is not just key-value based!
     OriginForm{
@@ -56,10 +66,13 @@ pub enum HttpRequestTarget {
     AsteriskForm,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[derive(PartialEq)]
 pub struct HttpConnectionMetaData {
     pub http_version: HttpVersion,
+    pub keep_alive: bool,
+    // every response to HEAD is sent without its content, whichever way it was generated
+    pub is_head_request: bool,
 }
 
 #[derive(Debug)]
@@ -69,16 +82,16 @@ pub enum HttpScheme {
     Https,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[derive(PartialEq)]
 pub struct HttpVersion {
     pub major: u8,
     pub minor: u8,
 }
 
-impl ToString for HttpVersion {
-    fn to_string(&self) -> String {
-        format!("HTTP/{}.{}", self.major, self.minor)
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HTTP/{}.{}", self.major, self.minor)
     }
 }
 
@@ -89,9 +102,9 @@ pub struct HttpHeader {
     pub field_value: String,
 }
 
-impl ToString for HttpHeader {
-    fn to_string(&self) -> String {
-        format!("{}: {}\r\n", self.field_name, self.field_value)
+impl fmt::Display for HttpHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}\r\n", self.field_name, self.field_value)
     }
 }
 
@@ -100,53 +113,210 @@ pub struct HttpRequest {
     pub request_target: HttpRequestTarget,
     pub meta_data: HttpConnectionMetaData,
     pub headers: Vec<HttpHeader>,
-    _content: Option<String>,
+    // the body itself stays on the connection until it's read with a BodyReader
+    pub body_framing: BodyFraming,
 }
 
 
 impl HttpRequest {
-    pub fn new(stream: &mut TcpStream, config: &Config) -> Result<HttpRequest, HttpParsingError> {
-        stream.set_read_timeout(Some(config.timeout)).unwrap();
-        let mut buffered_reader = BufReader::new(stream);
+    pub async fn new<R>(buffered_reader: &mut R, config: &Config) -> Result<HttpRequest, HttpParsingError>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        match time::timeout(config.timeout, Self::parse(buffered_reader)).await {
+            Ok(request) => request,
+            Err(_) => Err(HttpParsingError::Timeout),
+        }
+    }
+
+    async fn parse<R>(buffered_reader: &mut R) -> Result<HttpRequest, HttpParsingError>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let mut scanner = HeadScanner::default();
+        // only needed if the head doesn't arrive in one piece
+        let mut head_buffer = vec![];
+
+        loop {
+            let available = buffered_reader.fill_buf().await.map_err(|_| HttpParsingError::TcpIssue())?;
+            if available.is_empty() {
+                return Err(HttpParsingError::TcpIssue());
+            }
+
+            if head_buffer.is_empty() {
+                // usually the whole head is there at once and gets parsed right out of the read buffer
+                if let Some(head_length) = scanner.scan(available)? {
+                    let request = Self::from_head(parser::parse_head(&available[..head_length])?)?;
+                    buffered_reader.consume(head_length);
+                    return Ok(request);
+                }
+                head_buffer.extend_from_slice(available);
+                let available_length = available.len();
+                buffered_reader.consume(available_length);
+                continue;
+            }
+
+            let previous_length = head_buffer.len();
+            head_buffer.extend_from_slice(available);
+            match scanner.scan(&head_buffer)? {
+                Some(head_length) => {
+                    // whatever follows the head belongs to the next request and stays in the reader
+                    buffered_reader.consume(head_length - previous_length);
+                    return Self::from_head(parser::parse_head(&head_buffer[..head_length])?);
+                }
+                None => {
+                    let available_length = head_buffer.len() - previous_length;
+                    buffered_reader.consume(available_length);
+                }
+            }
+        }
+    }
 
-        let (method, request_target, http_version) = Self::parse_request_line(&mut buffered_reader)?;
-        let headers = Self::parse_headers(&mut buffered_reader)?;
+    // parses a complete request head at the start of the buffer, returning how many bytes it took up
+    pub fn from_bytes(buffer: &[u8]) -> Result<Option<(HttpRequest, usize)>, HttpParsingError> {
+        match HeadScanner::default().scan(buffer)? {
+            Some(head_length) => Ok(Some((Self::from_head(parser::parse_head(&buffer[..head_length])?)?, head_length))),
+            None => Ok(None),
+        }
+    }
+
+    fn from_head(head: RequestHead) -> Result<HttpRequest, HttpParsingError> {
+        let headers: Vec<HttpHeader> = head
+            .fields
+            .iter()
+            .map(|field| HttpHeader {
+                field_name: String::from(field.name),
+                field_value: String::from(field.value),
+            })
+            .collect();
+        let keep_alive = Self::is_connection_persistent(&head.version, &headers);
+        let body_framing = Self::parse_body_framing(&headers)?;
+        let method = Self::parse_method(head.method);
+        let is_head_request = method == HttpMethod::HEAD;
 
         Ok(HttpRequest {
             method,
-            request_target,
+            request_target: Self::parse_target_path(head.target)?,
+            meta_data: HttpConnectionMetaData {
+                http_version: head.version,
+                keep_alive,
+                is_head_request,
+            },
+            headers,
+            body_framing,
+        })
+    }
+
+    // protocols that frame requests themselves (ex. HTTP/2) only share the request semantics,
+    // the connection is kept open by their own means
+    pub fn from_parts(
+        method: &str,
+        target: &str,
+        http_version: HttpVersion,
+        headers: Vec<HttpHeader>,
+    ) -> Result<HttpRequest, HttpParsingError> {
+        if target.is_empty() {
+            return Err(HttpParsingError::InvalidTargetFormat);
+        }
+
+        let method = Self::parse_method(method);
+        let is_head_request = method == HttpMethod::HEAD;
+
+        Ok(HttpRequest {
+            method,
+            request_target: Self::parse_target_path(target)?,
             meta_data: HttpConnectionMetaData {
                 http_version,
+                keep_alive: true,
+                is_head_request,
             },
             headers,
-            _content: None,
+            body_framing: BodyFraming::None,
         })
-        
     }
-    
-    fn parse_request_line(buffered_reader: &mut BufReader<&mut TcpStream>) -> Result<(HttpMethod, HttpRequestTarget, HttpVersion), HttpParsingError> {
-        let mut request_line_buffer = String::new();
-        match buffered_reader.read_line(&mut request_line_buffer) {
-            Err(_) | Ok(0) => return Err(HttpParsingError::TcpIssue()),
-            _ => {},
+
+    pub fn get_header(&self, field_name: &str) -> Option<&str> {
+        Self::find_header(&self.headers, field_name)
+    }
+
+    fn find_header<'a>(headers: &'a [HttpHeader], field_name: &str) -> Option<&'a str> {
+        headers.iter()
+            .find(|header| header.field_name.eq_ignore_ascii_case(field_name))
+            .map(|header| header.field_value.trim())
+    }
+
+    // comma separated list values of all headers with this name, as in rfc9110 section 5.6.1
+    fn find_list_values<'a>(headers: &'a [HttpHeader], field_name: &'a str) -> impl Iterator<Item = &'a str> {
+        headers.iter()
+            .filter(move |header| header.field_name.eq_ignore_ascii_case(field_name))
+            .flat_map(|header| header.field_value.split(','))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    // connection persistence as described in rfc7230 section 6.3
+    fn is_connection_persistent(http_version: &HttpVersion, headers: &[HttpHeader]) -> bool {
+        // HTTP/1.0 doesn't know Transfer-Encoding, so a client might frame the body differently (rfc9112 section 6.1)
+        if http_version.major == 1 && http_version.minor == 0 && Self::find_header(headers, "Transfer-Encoding").is_some() {
+            return false;
         }
-        
-        let mut elements = request_line_buffer.split(" ");
 
-        let method = match elements.next() {
-            Some(method_string) => Self::parse_method(method_string),
-            None => return Err(HttpParsingError::InvalidMethodFormat),
-        };
-        let target = match elements.next() {
-            Some(target_path_string) => Self::parse_target_path(target_path_string)?,
-            None => return Err(HttpParsingError::InvalidTargetFormat),
-        };
-        let version = match elements.next() {
-            Some(version_string) => Self::parse_http_version(version_string.trim_end())?,
-            None => return Err(HttpParsingError::InvalidVersionFormat),
+        let has_connection_option = |option: &str| {
+            Self::find_list_values(headers, "Connection").any(|value| value.eq_ignore_ascii_case(option))
         };
 
-        Ok((method, target, version))
+        if has_connection_option("close") {
+            false
+        } else if http_version.major > 1 || (http_version.major == 1 && http_version.minor >= 1) {
+            true
+        } else {
+            has_connection_option("keep-alive")
+        }
+    }
+
+    // message body length as described in rfc9112 section 6.3, requests that could be read in more than one way
+    // are rejected, as a proxy in front of us might have read them the other way (request smuggling)
+    fn parse_body_framing(headers: &[HttpHeader]) -> Result<BodyFraming, HttpParsingError> {
+        let has_content_length = Self::find_header(headers, "Content-Length").is_some();
+
+        if Self::find_header(headers, "Transfer-Encoding").is_some() {
+            if has_content_length {
+                return Err(HttpParsingError::AmbiguousBodyLength);
+            }
+            let transfer_codings: Vec<&str> = Self::find_list_values(headers, "Transfer-Encoding").collect();
+            // without chunked as the final coding, only closing the connection would end the body
+            return match transfer_codings.split_last() {
+                Some((last, rest)) if last.eq_ignore_ascii_case("chunked") => {
+                    if rest.iter().any(|coding| coding.eq_ignore_ascii_case("chunked")) {
+                        Err(HttpParsingError::AmbiguousBodyLength)
+                    } else if !rest.is_empty() {
+                        Err(HttpParsingError::UnsupportedTransferCoding)
+                    } else {
+                        Ok(BodyFraming::Chunked)
+                    }
+                }
+                _ => Err(HttpParsingError::AmbiguousBodyLength),
+            };
+        }
+
+        if !has_content_length {
+            return Ok(BodyFraming::None);
+        }
+        // repeated values are fine as long as they all agree
+        let mut content_length = None;
+        for value in Self::find_list_values(headers, "Content-Length") {
+            if !value.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(HttpParsingError::InvalidHeaderFormat);
+            }
+            let length: u64 = value.parse().map_err(|_| HttpParsingError::InvalidHeaderFormat)?;
+            if content_length.is_some_and(|content_length| content_length != length) {
+                return Err(HttpParsingError::AmbiguousBodyLength);
+            }
+            content_length = Some(length);
+        }
+        content_length
+            .map(BodyFraming::ContentLength)
+            .ok_or(HttpParsingError::InvalidHeaderFormat)
     }
 
     fn parse_method(method: &str) -> HttpMethod {
@@ -165,69 +335,62 @@ impl HttpRequest {
     }
 
     fn parse_target_path(target_path: &str) -> Result<HttpRequestTarget, HttpParsingError> {
-        match target_path.chars().next().unwrap() {
-            '/' => {
-                Self::parse_origin_form(target_path)
-            },
-            'h' => {
-                match Self::parse_absolute_form(target_path) {
-                    Ok(request_target) => Ok(request_target),
-                    Err(_) => Self::parse_authority_form(target_path),
-                }
-            },
-            '*' => {
-                Self::parse_asterisk_form(target_path)
-            },
-            _ => {
-                Self::parse_authority_form(target_path)
+        match target_path.as_bytes().first() {
+            None => Err(HttpParsingError::InvalidTargetFormat),
+            Some(b'/') => Self::parse_origin_form(target_path),
+            Some(b'h') | Some(b'H') => match Self::parse_absolute_form(target_path) {
+                Ok(request_target) => Ok(request_target),
+                Err(_) => Self::parse_authority_form(target_path),
             },
+            Some(b'*') => Self::parse_asterisk_form(target_path),
+            Some(_) => Self::parse_authority_form(target_path),
         }
     }
 
-    fn parse_origin_form(target_path: &str) -> Result<HttpRequestTarget, HttpParsingError> {
-        lazy_static! {
-            static ref ORIGIN_FORM_REGEX: Regex = Regex::new(r"^([^?[:space:]]*)(?:\?([^#]*))?$").unwrap();
+    // fragments are never sent and whitespace already ends the target in the request line,
+    // but targets of other protocols (ex. HTTP/2) only get checked here
+    fn is_valid_target(target_path: &str) -> bool {
+        target_path.bytes().all(|byte| byte.is_ascii_graphic() && byte != b'#')
+    }
+
+    fn split_query(target_path: &str) -> (&str, Option<String>) {
+        match target_path.split_once('?') {
+            Some((path, query)) => (path, Some(String::from(query))),
+            None => (target_path, None),
         }
-        let captures = match ORIGIN_FORM_REGEX.captures(target_path) {
-            Some(captures) => captures,
-            None => return Err(HttpParsingError::InvalidTargetFormat),
-        };
+    }
 
-        let path = String::from(&captures[1]);
-        let query = match captures.get(2) {
-            Some(rx_match) => Some(String::from(rx_match.as_str())),
-            None => None
-        };
+    fn parse_origin_form(target_path: &str) -> Result<HttpRequestTarget, HttpParsingError> {
+        if !Self::is_valid_target(target_path) {
+            return Err(HttpParsingError::InvalidTargetFormat);
+        }
+        let (path, query) = Self::split_query(target_path);
 
         Ok(HttpRequestTarget::OriginForm{
-            path, 
+            path: String::from(path),
             query,
         })
     }
     
     fn parse_absolute_form(target_path: &str) -> Result<HttpRequestTarget, HttpParsingError> {
-        lazy_static! {
-            static ref ABSOLTE_FORM_REGEX: Regex = Regex::new(r"^(http|https)://((?:\w|\.)+(?::\d+)?)((?:/|\w)*)?(?:\?([^#]*))?$").unwrap();
-        }
-        let captures = match ABSOLTE_FORM_REGEX.captures(target_path) {
-            Some(captures) => captures,
-            None => return Err(HttpParsingError::InvalidTargetFormat),
-        };
-        let scheme = match &captures[1] {
-            "http" => HttpScheme::Http,
-            "https" => HttpScheme::Https,
+        let (scheme, rest) = match target_path.split_once("://") {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => (HttpScheme::Http, rest),
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => (HttpScheme::Https, rest),
             _ => return Err(HttpParsingError::UnknownScheme),
         };
-        let authority = String::from(&captures[2]);
-        let path = match captures.get(3) {
-            Some(rx_match) => Some(String::from(rx_match.as_str())),
-            None => None
-        };
-        let query = match captures.get(4) {
-            Some(rx_match) => Some(String::from(rx_match.as_str())),
-            None => None
-        };
-        Ok(HttpRequestTarget::AbsoluteForm{scheme, path, authority, query})
+        if !Self::is_valid_target(rest) {
+            return Err(HttpParsingError::InvalidTargetFormat);
+        }
+
+        let authority_end = rest.find(&['/', '?'][..]).unwrap_or(rest.len());
+        let authority = &rest[..authority_end];
+        if !Self::is_valid_authority(authority) {
+            return Err(HttpParsingError::InvalidTargetFormat);
+        }
+        let (path, query) = Self::split_query(&rest[authority_end..]);
+        let path = Some(path).filter(|path| !path.is_empty()).map(String::from);
+
+        Ok(HttpRequestTarget::AbsoluteForm{scheme, path, authority: String::from(authority), query})
     }
 
     fn parse_asterisk_form(target_path: &str) -> Result<HttpRequestTarget, HttpParsingError> {
@@ -239,94 +402,77 @@ impl HttpRequest {
     }
 
     fn parse_authority_form(target_path: &str) -> Result<HttpRequestTarget, HttpParsingError> {
-        lazy_static! {
-            static ref AUTHORITY_FORM_REGEX: Regex = Regex::new(r"^((?:\w|\.)+(?::\d+)?)$").unwrap();
+        if !Self::is_valid_authority(target_path) {
+            return Err(HttpParsingError::InvalidTargetFormat);
         }
-        let captures = match AUTHORITY_FORM_REGEX.captures(target_path) {
-            Some(captures) => captures,
-            None => return Err(HttpParsingError::InvalidTargetFormat),
-        };
 
-        let authority = String::from(&captures[1]);
-
-        Ok(HttpRequestTarget::AuthorityForm{authority})
+        Ok(HttpRequestTarget::AuthorityForm{authority: String::from(target_path)})
     }
 
-    fn parse_http_version(http_version: &str) -> Result<HttpVersion, HttpParsingError> {
-        lazy_static! {
-            static ref HTTP_VERSION_REGEX: Regex = Regex::new(r"^HTTP/(\d)\.(\d)$").unwrap();
-        }
-
-        let captures = match HTTP_VERSION_REGEX.captures(http_version) {
-            Some(captures) => captures,
-            None => return Err(HttpParsingError::InvalidSyntax),
+    // host and optional port as in rfc3986 section 3.2, user info is deprecated for http(s) and rejected
+    fn is_valid_authority(authority: &str) -> bool {
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(ip_literal) => match ip_literal.split_once(']') {
+                Some((address, port)) => {
+                    let is_valid_address = !address.is_empty()
+                        && address.bytes().all(|byte| byte.is_ascii_hexdigit() || byte == b':' || byte == b'.');
+                    if !is_valid_address {
+                        return false;
+                    }
+                    (None, port)
+                }
+                None => return false,
+            },
+            None => match authority.rfind(':') {
+                Some(port_start) => (Some(&authority[..port_start]), &authority[port_start..]),
+                None => (Some(authority), ""),
+            },
         };
-        
-        // single digit numbers should allways be parseable as u8
-        let major = captures[1].parse::<u8>().unwrap();
-        let minor = captures[1].parse::<u8>().unwrap();
-
-        Ok(HttpVersion {major, minor})
-    }
-
-
-    fn parse_headers(buffered_reader: &mut BufReader<&mut TcpStream>) -> Result<Vec<HttpHeader>, HttpParsingError> {
-        lazy_static!{
-            // ignoring obs-folds as specified within Http/1.1 spec
-            static ref HEADER_REGEX: Regex = Regex::new(r"^([^:[:space:]]+):(.+)$").unwrap();
-        }
-        
-        let mut headers = vec![];
-
-        loop {
-            let mut request_line_buffer = String::new();
-            match buffered_reader.read_line(&mut request_line_buffer) {
-                Err(_) | Ok(0) => return Err(HttpParsingError::TcpIssue()),
-                _ => {},
-            }
-            // Remove trailing "\r\n"
-            let request_line = &request_line_buffer[0..request_line_buffer.len() - 2];
-            
-            // catch end of header and continue
-            if request_line.len() == 0 {
-                break;
-            }
-
-            let captures = match HEADER_REGEX.captures(request_line) {
-                Some(captures) => captures,
-                None => return Err(HttpParsingError::InvalidHeaderFormat),
-            };
-
-            let field_name = String::from(&captures[1]);
-            let field_value = String::from(&captures[2]);
-
-            // TODO: potentially list-concatenate headers as in rfc7230
 
-            headers.push(HttpHeader { field_name, field_value })
-        }
-        
-        Ok(headers)
+        const HOST_SYMBOLS: &[u8] = b"-._~!$&'()*+,;=%";
+        let is_valid_host = host.is_none_or(|host| {
+            !host.is_empty() && host.bytes().all(|byte| byte.is_ascii_alphanumeric() || HOST_SYMBOLS.contains(&byte))
+        });
+        let is_valid_port = match port.strip_prefix(':') {
+            Some(port) => !port.is_empty() && port.bytes().all(|byte| byte.is_ascii_digit()),
+            None => port.is_empty(),
+        };
+        is_valid_host && is_valid_port
     }
 
 }
 
-impl ToString for HttpRequest {
-    fn to_string(&self) -> String {
-        format!("{:?}, {:?}", self.method, self.request_target)
+impl fmt::Display for HttpRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}, {:?}", self.method, self.request_target)
     }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use crate::configuration::Config;
     use crate::http::{request::{HttpParsingError, HttpScheme}, HttpRequest};
 
-    use super::{HttpMethod, HttpRequestTarget, HttpVersion};
+    use super::{parser, BodyFraming, HttpHeader, HttpMethod, HttpRequestTarget, HttpVersion};
+
+    fn parse_request_line(request_line: &str) -> Result<(HttpMethod, HttpRequestTarget, HttpVersion), HttpParsingError> {
+        let raw_request = format!("{}\r\n\r\n", request_line);
+        let (request, _) = HttpRequest::from_bytes(raw_request.as_bytes())?.unwrap();
+        Ok((request.method, request.request_target, request.meta_data.http_version))
+    }
+
+    fn connection_header(value: &str) -> HttpHeader {
+        HttpHeader {
+            field_name: String::from("Connection"),
+            field_value: String::from(value),
+        }
+    }
 
     #[test]
     fn parses_correct_method() {
         let request_line = "GET * HTTP/1.1";
-        let result = HttpRequest::parse_request_line(request_line);
+        let result = parse_request_line(request_line);
         let expected = Ok((HttpMethod::GET, HttpRequestTarget::AsteriskForm, HttpVersion{major: 1, minor: 1}));
         assert_eq!(result, expected);
     }
@@ -334,7 +480,7 @@ mod tests {
     #[test]
     fn gives_unknown_method_when_providing_invalid_method() {
         let request_line = "GLOOP * HTTP/1.1";
-        let result = HttpRequest::parse_request_line(request_line);
+        let result = parse_request_line(request_line);
         let expected = Ok((HttpMethod::UnknownMethod, HttpRequestTarget::AsteriskForm, HttpVersion{major: 1, minor: 1}));
         assert_eq!(result, expected);
     }
@@ -342,7 +488,7 @@ mod tests {
     #[test]
     fn gives_error_when_providing_invalid_request_line() {
         let invalid_request_line = "GET * from a good website";
-        let result = HttpRequest::parse_request_line(invalid_request_line);
+        let result = parse_request_line(invalid_request_line);
         let expected = Err(HttpParsingError::InvalidSyntax);
         assert_eq!(result, expected);
     }
@@ -428,7 +574,7 @@ mod tests {
     #[test]
     fn parses_http_version_correctly() {
         let http_version = "HTTP/1.1";
-        let result = HttpRequest::parse_http_version(http_version);
+        let result = parser::parse_version(http_version.as_bytes());
         let expected = Ok(HttpVersion{major: 1, minor: 1});
         assert_eq!(result, expected);
     }
@@ -436,10 +582,136 @@ mod tests {
     #[test]
     fn gives_correct_error_when_parsing_http_version() {
         let http_version = "Amos/1.1";
-        let result = HttpRequest::parse_http_version(http_version);
+        let result = parser::parse_version(http_version.as_bytes());
         let expected = Err(HttpParsingError::InvalidSyntax);
         assert_eq!(result, expected);
     }
-}
 
+    #[test]
+    fn parses_http_1_0_version_correctly() {
+        let http_version = "HTTP/1.0";
+        let result = parser::parse_version(http_version.as_bytes());
+        let expected = Ok(HttpVersion{major: 1, minor: 0});
+        assert_eq!(result, expected);
+    }
 
+    #[test]
+    fn http_1_1_connection_is_persistent_by_default() {
+        let version = HttpVersion{major: 1, minor: 1};
+        assert!(HttpRequest::is_connection_persistent(&version, &[]));
+        assert!(!HttpRequest::is_connection_persistent(&version, &[connection_header(" Close")]));
+    }
+
+    #[test]
+    fn http_1_0_connection_requires_keep_alive() {
+        let version = HttpVersion{major: 1, minor: 0};
+        assert!(!HttpRequest::is_connection_persistent(&version, &[]));
+        assert!(HttpRequest::is_connection_persistent(&version, &[connection_header("Keep-Alive")]));
+    }
+
+    #[test]
+    fn connection_with_body_stays_persistent() {
+        let content_length = HttpHeader {
+            field_name: String::from("Content-Length"),
+            field_value: String::from("12"),
+        };
+        assert!(HttpRequest::is_connection_persistent(&HttpVersion{major: 1, minor: 1}, &[content_length]));
+
+        let transfer_encoding = HttpHeader {
+            field_name: String::from("Transfer-Encoding"),
+            field_value: String::from("chunked"),
+        };
+        assert!(!HttpRequest::is_connection_persistent(&HttpVersion{major: 1, minor: 0}, &[transfer_encoding]));
+    }
+
+    #[test]
+    fn finds_body_framing() {
+        let framing_of = |fields: &str| {
+            let raw_request = format!("POST / HTTP/1.1\r\n{}\r\n", fields);
+            HttpRequest::from_bytes(raw_request.as_bytes()).map(|request| request.unwrap().0.body_framing)
+        };
+
+        assert_eq!(framing_of(""), Ok(BodyFraming::None));
+        assert_eq!(framing_of("Content-Length: 42\r\n"), Ok(BodyFraming::ContentLength(42)));
+        assert_eq!(framing_of("Content-Length: 42, 42\r\nContent-Length: 42\r\n"), Ok(BodyFraming::ContentLength(42)));
+        assert_eq!(framing_of("Transfer-Encoding: Chunked\r\n"), Ok(BodyFraming::Chunked));
+    }
+
+    #[test]
+    fn rejects_ambiguous_body_framing() {
+        let invalid_fields = vec![
+            ("Content-Length: 42\r\nTransfer-Encoding: chunked\r\n", HttpParsingError::AmbiguousBodyLength),
+            ("Content-Length: 42\r\nContent-Length: 43\r\n", HttpParsingError::AmbiguousBodyLength),
+            ("Transfer-Encoding: chunked, chunked\r\n", HttpParsingError::AmbiguousBodyLength),
+            ("Transfer-Encoding: chunked, gzip\r\n", HttpParsingError::AmbiguousBodyLength),
+            ("Transfer-Encoding: gzip, chunked\r\n", HttpParsingError::UnsupportedTransferCoding),
+            ("Content-Length: +42\r\n", HttpParsingError::InvalidHeaderFormat),
+            ("Content-Length: 99999999999999999999\r\n", HttpParsingError::InvalidHeaderFormat),
+        ];
+
+        for (fields, error) in invalid_fields {
+            let raw_request = format!("POST / HTTP/1.1\r\n{}\r\n", fields);
+            assert_eq!(HttpRequest::from_bytes(raw_request.as_bytes()).err(), Some(error), "{}", fields);
+        }
+    }
+
+    #[tokio::test]
+    async fn parses_request_from_stream() {
+        let config = Config::new(&[String::from("throwscape")]).unwrap();
+        let mut raw_request: &[u8] = b"GET /test.html HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\n";
+
+        let request = HttpRequest::new(&mut raw_request, &config).await.unwrap();
+
+        assert_eq!(request.method, HttpMethod::GET);
+        assert_eq!(request.get_header("host"), Some("localhost"));
+        assert_eq!(raw_request, b"GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn builds_request_from_parts() {
+        let request = HttpRequest::from_parts("HEAD", "/index.html?v=2", HttpVersion{major: 2, minor: 0}, vec![]).unwrap();
+        let expected_target = HttpRequestTarget::OriginForm{
+            path: String::from("/index.html"),
+            query: Some(String::from("v=2")),
+        };
+        assert_eq!(request.method, HttpMethod::HEAD);
+        assert_eq!(request.request_target, expected_target);
+        assert!(request.meta_data.keep_alive);
+
+        let result = HttpRequest::from_parts("GET", "", HttpVersion{major: 2, minor: 0}, vec![]);
+        assert_eq!(result.err(), Some(HttpParsingError::InvalidTargetFormat));
+    }
+
+    #[test]
+    fn rejects_versions_other_than_http_1() {
+        assert_eq!(parser::parse_version(b"HTTP/2.0"), Err(HttpParsingError::UnsupportedVersion));
+        assert_eq!(parse_request_line("GET  HTTP/1.1"), Err(HttpParsingError::InvalidTargetFormat));
+    }
+
+    #[tokio::test]
+    async fn limits_request_line_and_header_size() {
+        let config = Config::new(&[String::from("throwscape")]).unwrap();
+
+        let raw_request = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10_000));
+        let result = HttpRequest::new(&mut raw_request.as_bytes(), &config).await;
+        assert_eq!(result.err(), Some(HttpParsingError::TargetTooLong));
+
+        let raw_request = format!("GET / HTTP/1.1\r\n{}\r\n", "Cookie: a=b\r\n".repeat(2_000));
+        let result = HttpRequest::new(&mut raw_request.as_bytes(), &config).await;
+        assert_eq!(result.err(), Some(HttpParsingError::HeadersTooLarge));
+
+        let mut raw_request: &[u8] = b"GET /\xff HTTP/1.1\r\n\r\n";
+        let result = HttpRequest::new(&mut raw_request, &config).await;
+        assert_eq!(result.err(), Some(HttpParsingError::InvalidTargetFormat));
+    }
+
+    #[tokio::test]
+    async fn times_out_on_incomplete_request() {
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.timeout = std::time::Duration::from_millis(20);
+        let (_client, server) = tokio::io::duplex(64);
+
+        let result = HttpRequest::new(&mut tokio::io::BufReader::new(server), &config).await;
+        assert_eq!(result.err(), Some(HttpParsingError::Timeout));
+    }
+}
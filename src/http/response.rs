@@ -1,71 +1,296 @@
-use std::fs::File;
-use std::io::{copy, BufReader, Error, Write};
-use std::net::TcpStream;
+use std::fmt;
+use std::fs;
+use std::io::{Error, SeekFrom};
 use std::path::{Path, PathBuf};
 
-use crate::configuration::Config;
+use bytes::Bytes;
+use tokio::fs::File;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
 
-use super::request::{HttpConnectionMetaData, HttpHeader, HttpRequestTarget};
+use crate::configuration::{self, Config, HeaderRule};
+
+use super::chunked;
+use super::compression;
+use super::conditional::{self, Precondition, Validators};
+use super::encoding::{self, ContentEncoding, EncodingSelection};
+use super::mime;
+use super::range::{self, PartialContent, RangeSelection};
+use super::request::{HttpConnectionMetaData, HttpHeader, HttpParsingError, HttpRequestTarget, HttpVersion};
 use super::{HttpMethod, HttpRequest};
 
+// a full queue usually clears up within moments
+const RETRY_AFTER_SECONDS: u64 = 1;
+
 #[derive(Debug, PartialEq)]
 pub struct HttpResponse {
     status: HttpStatus,
     meta_data: HttpConnectionMetaData,
     headers: Vec<HttpHeader>,
-    content: HttpMessageContent,
+    content: Option<HttpMessageContent>,
+    partial_content: Option<PartialContent>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum HttpStatus {
     Okay,
+    PartialContent,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    TemporaryRedirect,
+    PermanentRedirect,
     BadRequest,
     MethodNotAllowed,
     FileNotFound,
-    _InternalServerError,
+    PreconditionFailed,
+    ContentTooLarge,
+    RangeNotSatisfiable,
+    InternalServerError,
+    RequestTimeout,
+    UriTooLong,
+    RequestHeaderFieldsTooLarge,
     NotImplemented,
+    ServiceUnavailable,
+    HttpVersionNotSupported,
 }
 
 impl HttpStatus {
     pub fn as_code(&self) -> i32 {
         match &self {
             HttpStatus::Okay => 200,
+            HttpStatus::PartialContent => 206,
+            HttpStatus::MovedPermanently => 301,
+            HttpStatus::Found => 302,
+            HttpStatus::SeeOther => 303,
+            HttpStatus::NotModified => 304,
+            HttpStatus::TemporaryRedirect => 307,
+            HttpStatus::PermanentRedirect => 308,
             HttpStatus::BadRequest => 400,
             HttpStatus::MethodNotAllowed => 405,
             HttpStatus::FileNotFound => 404,
-            HttpStatus::_InternalServerError => 500,
+            HttpStatus::PreconditionFailed => 412,
+            HttpStatus::RequestTimeout => 408,
+            HttpStatus::ContentTooLarge => 413,
+            HttpStatus::UriTooLong => 414,
+            HttpStatus::RangeNotSatisfiable => 416,
+            HttpStatus::RequestHeaderFieldsTooLarge => 431,
+            HttpStatus::InternalServerError => 500,
             HttpStatus::NotImplemented => 501,
+            HttpStatus::ServiceUnavailable => 503,
+            HttpStatus::HttpVersionNotSupported => 505,
         }
     }
     pub fn as_reason_statement(&self) -> &str {
         match &self {
             HttpStatus::Okay => "OK",
+            HttpStatus::PartialContent => "Partial Content",
+            HttpStatus::MovedPermanently => "Moved Permanently",
+            HttpStatus::Found => "Found",
+            HttpStatus::SeeOther => "See Other",
+            HttpStatus::NotModified => "Not Modified",
+            HttpStatus::TemporaryRedirect => "Temporary Redirect",
+            HttpStatus::PermanentRedirect => "Permanent Redirect",
             HttpStatus::BadRequest => "Bad Request",
             HttpStatus::MethodNotAllowed => "Method Not Allowed",
             HttpStatus::FileNotFound => "File Not Found",
+            HttpStatus::PreconditionFailed => "Precondition Failed",
+            HttpStatus::RequestTimeout => "Request Timeout",
+            HttpStatus::ContentTooLarge => "Content Too Large",
+            HttpStatus::UriTooLong => "URI Too Long",
+            HttpStatus::RangeNotSatisfiable => "Range Not Satisfiable",
+            HttpStatus::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
             HttpStatus::NotImplemented => "Not Implemented",
-            HttpStatus::_InternalServerError => "Internal Server Error",
+            HttpStatus::InternalServerError => "Internal Server Error",
+            HttpStatus::ServiceUnavailable => "Service Unavailable",
+            HttpStatus::HttpVersionNotSupported => "HTTP Version Not Supported",
         }
     }
     pub fn is_error(&self) -> bool {
         self.as_code() >= 400
     }
+    pub fn from_redirect_code(code: u16) -> Option<HttpStatus> {
+        match code {
+            301 => Some(HttpStatus::MovedPermanently),
+            302 => Some(HttpStatus::Found),
+            303 => Some(HttpStatus::SeeOther),
+            307 => Some(HttpStatus::TemporaryRedirect),
+            308 => Some(HttpStatus::PermanentRedirect),
+            _ => None,
+        }
+    }
+    // None if there is nobody left to answer, ex. because the connection was closed
+    pub fn from_parsing_error(error: &HttpParsingError) -> Option<HttpStatus> {
+        match error {
+            HttpParsingError::TcpIssue() => None,
+            HttpParsingError::Timeout => Some(HttpStatus::RequestTimeout),
+            HttpParsingError::TargetTooLong => Some(HttpStatus::UriTooLong),
+            HttpParsingError::HeadersTooLarge => Some(HttpStatus::RequestHeaderFieldsTooLarge),
+            HttpParsingError::UnsupportedVersion => Some(HttpStatus::HttpVersionNotSupported),
+            HttpParsingError::BodyTooLarge => Some(HttpStatus::ContentTooLarge),
+            HttpParsingError::UnknownMethod | HttpParsingError::UnsupportedTransferCoding => Some(HttpStatus::NotImplemented),
+            HttpParsingError::InvalidSyntax
+            | HttpParsingError::InvalidMethodFormat
+            | HttpParsingError::InvalidTargetFormat
+            | HttpParsingError::InvalidVersionFormat
+            | HttpParsingError::InvalidHeaderFormat
+            | HttpParsingError::InvalidChunkFormat
+            | HttpParsingError::AmbiguousBodyLength
+            | HttpParsingError::UnknownScheme => Some(HttpStatus::BadRequest),
+        }
+    }
     pub fn get_reason_statement_len(&self) -> u64 {
         self.as_reason_statement().len() as u64
     }
 }
 
-type HttpMessageContent = Option<Box<Path>>;
+// content that is generated while it's sent (ex. a directory listing), so its length isn't known up front
+pub trait ContentStream: AsyncRead + Send + Unpin {
+    // fields sent after the content (ex. a checksum), only asked for once the content was read to its end
+    fn trailers(&mut self) -> Vec<HttpHeader> {
+        vec![]
+    }
+}
+
+// responses can be built from any of these, only files served from the source directory need the file system
+pub enum HttpMessageContent {
+    Bytes(Bytes),
+    // an already opened file, of which length bytes starting at offset are sent
+    File {
+        file: File,
+        offset: u64,
+        length: u64,
+    },
+    Stream(Box<dyn ContentStream>),
+}
+
+impl HttpMessageContent {
+    pub fn from_file(file: fs::File, offset: u64, length: u64) -> HttpMessageContent {
+        HttpMessageContent::File { file: File::from_std(file), offset, length }
+    }
+
+    // None for streams, their length is only known once they were read
+    pub fn content_length(&self) -> Option<u64> {
+        match self {
+            HttpMessageContent::Bytes(bytes) => Some(bytes.len() as u64),
+            HttpMessageContent::File { length, .. } => Some(*length),
+            HttpMessageContent::Stream(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for HttpMessageContent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpMessageContent::Bytes(bytes) => write!(f, "Bytes({:?})", bytes),
+            HttpMessageContent::File { offset, length, .. } => write!(f, "File {{ offset: {}, length: {} }}", offset, length),
+            HttpMessageContent::Stream(_) => write!(f, "Stream"),
+        }
+    }
+}
+
+// files and streams can't be compared without reading them
+impl PartialEq for HttpMessageContent {
+    fn eq(&self, other: &HttpMessageContent) -> bool {
+        match (self, other) {
+            (HttpMessageContent::Bytes(bytes), HttpMessageContent::Bytes(other_bytes)) => bytes == other_bytes,
+            _ => false,
+        }
+    }
+}
 
 impl HttpResponse {
     pub fn new(request: HttpRequest, config: &Config) -> HttpResponse {
-        let path = match &request.request_target {
-            HttpRequestTarget::OriginForm { path, .. }
-            | HttpRequestTarget::AbsoluteForm {
-                path: Some(path), ..
-            } => path,
-            HttpRequestTarget::AbsoluteForm { path: None, .. } => "/",
-            _ => {
+        let header_rules: Vec<&HeaderRule> = match Self::get_request_path(&request.request_target) {
+            Some(path) => config.header_rules.iter().filter(|rule| rule.matches(path)).collect(),
+            None => vec![],
+        };
+
+        let mut response = Self::resolve(request, config);
+        if !response.status.is_error() {
+            for (field_name, field_value) in header_rules.into_iter().flat_map(|rule| rule.headers.iter()) {
+                response.headers.push(HttpHeader {
+                    field_name: field_name.clone(),
+                    field_value: field_value.clone(),
+                });
+            }
+        }
+        response
+    }
+
+    // built without touching the file system, so turning requests away stays cheap while the server is overloaded
+    pub fn new_overloaded(meta_data: HttpConnectionMetaData) -> HttpResponse {
+        let mut response = Self::generate_plain_response(HttpStatus::ServiceUnavailable, meta_data);
+        response.headers.push(HttpHeader {
+            field_name: String::from("Retry-After"),
+            field_value: RETRY_AFTER_SECONDS.to_string(),
+        });
+        response
+    }
+
+    // sent in place of a response that never got generated, ex. because its worker panicked
+    pub fn new_internal_error(meta_data: HttpConnectionMetaData) -> HttpResponse {
+        Self::generate_plain_response(HttpStatus::InternalServerError, meta_data)
+    }
+
+    // the request couldn't be read to its end, so the connection is closed afterwards
+    pub fn new_parsing_error(error: &HttpParsingError) -> Option<HttpResponse> {
+        let meta_data = HttpConnectionMetaData {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            keep_alive: false,
+            is_head_request: false,
+        };
+        HttpStatus::from_parsing_error(error).map(|status| Self::generate_plain_response(status, meta_data))
+    }
+
+    // content of unknown length is sent in chunks to HTTP/1.1 clients, HTTP/1.0 ones only see its end
+    // once the connection is closed
+    pub fn with_content(
+        status: HttpStatus,
+        mut meta_data: HttpConnectionMetaData,
+        content_type: String,
+        content: HttpMessageContent,
+    ) -> HttpResponse {
+        let mut headers = match content.content_length() {
+            Some(content_length) => Self::generate_response_headers(content_length, &meta_data),
+            None => {
+                let is_http_1_0 = meta_data.http_version.major == 1 && meta_data.http_version.minor == 0;
+                if is_http_1_0 {
+                    meta_data.keep_alive = false;
+                }
+
+                let mut headers = Self::generate_connection_headers(&meta_data);
+                if meta_data.http_version.major == 1 && !is_http_1_0 {
+                    headers.push(HttpHeader {
+                        field_name: String::from("Transfer-Encoding"),
+                        field_value: String::from("chunked"),
+                    });
+                }
+                headers
+            }
+        };
+        headers.push(HttpHeader {
+            field_name: String::from("Content-Type"),
+            field_value: content_type,
+        });
+        HttpResponse {
+            status,
+            meta_data,
+            headers,
+            content: Some(content),
+            partial_content: None,
+        }
+    }
+
+    fn generate_plain_response(status: HttpStatus, meta_data: HttpConnectionMetaData) -> HttpResponse {
+        let reason_statement = Bytes::copy_from_slice(status.as_reason_statement().as_bytes());
+        Self::with_content(status, meta_data, String::from(mime::PLAIN_TEXT), HttpMessageContent::Bytes(reason_statement))
+    }
+
+    fn resolve(request: HttpRequest, config: &Config) -> HttpResponse {
+        let path = match Self::get_request_path(&request.request_target) {
+            Some(path) => path,
+            None => {
                 return Self::generate_error_response(
                     HttpStatus::BadRequest,
                     request.meta_data,
@@ -74,19 +299,40 @@ impl HttpResponse {
             }
         };
 
-        let (content, len) = if request.method == HttpMethod::GET {
+        // redirects apply before anything is looked up on disk
+        let query = Self::get_request_query(&request.request_target);
+        let redirect = config
+            .redirects
+            .iter()
+            .find_map(|redirect| Some((redirect.status, redirect.get_location(path, query)?)));
+        if let Some((status, location)) = redirect {
+            return Self::generate_redirect_response(status, location, request.meta_data);
+        }
+
+        // the most specific route wins, everything else is served from the source directory
+        let (serve_path, path) = config
+            .routes
+            .iter()
+            .filter_map(|route| Some((route, route.strip_path(path)?)))
+            .max_by_key(|(route, _)| route.path.len())
+            .map(|(route, path)| (&route.serve_path, path))
+            .unwrap_or((&config.serve_path, path));
+
+        // HEAD goes through the same steps as GET, its content is only left out when sending
+        let (content_path, len, mut encoding_selection) = if request.method == HttpMethod::GET
+            || request.method == HttpMethod::HEAD
+        {
             match Self::pre_generate_message_content(
                 path,
-                &config.serve_path,
+                serve_path,
                 &config.index_file_name,
+                request.get_header("Accept-Encoding"),
             ) {
                 Err(status) => {
                     return Self::generate_error_response(status, request.meta_data, config)
                 }
                 Ok(message) => message,
             }
-        } else if request.method == HttpMethod::HEAD {
-            (None, 0)
         } else if request.method == HttpMethod::UnknownMethod {
             return Self::generate_error_response(
                 HttpStatus::NotImplemented,
@@ -101,79 +347,272 @@ impl HttpResponse {
             );
         };
 
-        let headers = Self::generate_response_headers(len);
+        // the media type is the one of the original file, not of its compressed sibling
+        let content_type = match encoding_selection.encoding {
+            ContentEncoding::Identity => mime::get_content_type(&content_path, config),
+            _ => mime::get_content_type(&content_path.with_extension(""), config),
+        };
+
+        let compressed_file = if encoding_selection.encoding == ContentEncoding::Identity
+            && config.is_compression_enabled
+            && len >= config.compression_min_size
+            && compression::is_compressible(&content_type)
+        {
+            encoding_selection.has_variants = true;
+            compression::get_compressed_file(&content_path, request.get_header("Accept-Encoding"), config)
+        } else {
+            None
+        };
+
+        // the file is opened once, its length and validators come from the open handle, so the content can't
+        // change in between (ex. with a deployment replacing the file)
+        let opened_file = match compressed_file {
+            Some((compressed_file, encoding)) => {
+                encoding_selection.encoding = encoding;
+                Self::with_file_meta_data(compressed_file)
+            }
+            None => Self::open_file(&content_path),
+        };
+        let (file, file_meta_data) = match opened_file {
+            Some(opened) => opened,
+            None => return Self::generate_error_response(HttpStatus::FileNotFound, request.meta_data, config),
+        };
+        let len = file_meta_data.len();
+        let validators = Validators::new(&file_meta_data);
+
+        if let Some(validators) = &validators {
+            match conditional::evaluate_preconditions(&request, validators) {
+                Precondition::Passed => {}
+                Precondition::NotModified => {
+                    let mut response = Self::generate_not_modified_response(request.meta_data, validators);
+                    response.headers.extend(encoding_selection.generate_headers());
+                    return response;
+                }
+                Precondition::Failed => {
+                    return Self::generate_error_response(
+                        HttpStatus::PreconditionFailed,
+                        request.meta_data,
+                        config,
+                    )
+                }
+            }
+        }
+
+        let range_selection = match request.get_header("Range") {
+            Some(range_header) if range::is_if_range_fulfilled(request.get_header("If-Range"), validators.as_ref()) => {
+                range::parse_range_header(range_header, len)
+            }
+            _ => RangeSelection::Full,
+        };
+
+        let (status, partial_content) = match range_selection {
+            RangeSelection::Full => (HttpStatus::Okay, None),
+            RangeSelection::Partial(ranges) => {
+                (HttpStatus::PartialContent, Some(PartialContent::new(ranges, len, content_type.clone())))
+            }
+            RangeSelection::NotSatisfiable => {
+                let mut response = Self::generate_error_response(
+                    HttpStatus::RangeNotSatisfiable,
+                    request.meta_data,
+                    config,
+                );
+                response.headers.push(HttpHeader {
+                    field_name: String::from("Content-Range"),
+                    field_value: format!("bytes */{}", len),
+                });
+                return response;
+            }
+        };
+
+        let mut headers = match &partial_content {
+            Some(partial_content) => {
+                let mut headers = Self::generate_response_headers(partial_content.content_length(), &request.meta_data);
+                headers.extend(partial_content.generate_headers());
+                headers
+            }
+            None => {
+                let mut headers = Self::generate_response_headers(len, &request.meta_data);
+                headers.push(HttpHeader {
+                    field_name: String::from("Content-Type"),
+                    field_value: content_type,
+                });
+                headers
+            }
+        };
+        headers.push(HttpHeader {
+            field_name: String::from("Accept-Ranges"),
+            field_value: String::from("bytes"),
+        });
+        headers.extend(encoding_selection.generate_headers());
+        if let Some(validators) = &validators {
+            headers.extend(validators.generate_headers());
+        }
 
         HttpResponse {
-            status: HttpStatus::Okay,
+            status,
             meta_data: request.meta_data,
             headers,
-            content,
+            content: Some(HttpMessageContent::from_file(file, 0, len)),
+            partial_content,
         }
     }
 
-    pub fn send(&self, stream: &mut TcpStream) -> Result<(), Error> {
+    pub async fn send<W>(&mut self, stream: &mut W) -> Result<(), Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
         const DELIMITER: &str = "\r\n";
 
-        stream.write(self.generate_status_line().as_bytes())?;
-        stream.write(DELIMITER.as_bytes())?;
+        // collect the head first so it doesn't end up in a separate packet per header
+        let mut head = self.generate_status_line();
+        head.push_str(DELIMITER);
 
         for header in self.headers.iter() {
-            stream.write(header.to_string().as_bytes())?;
+            head.push_str(&header.to_string());
         }
 
-        stream.write(DELIMITER.as_bytes())?;
-
-        let mut content_missing = true;
+        head.push_str(DELIMITER);
+        stream.write_all(head.as_bytes()).await?;
 
-        if let Some(content_path) = &self.content {
-            match File::open(content_path) {
-                Ok(path) => {
-                    copy(&mut BufReader::new(path), stream)?;
-                    content_missing = false;
-                }
-                Err(_) => {}
+        if self.is_chunked() && !self.meta_data.is_head_request {
+            if let Some(HttpMessageContent::Stream(content)) = &mut self.content {
+                chunked::write_chunks(content, stream).await?;
             }
-            let file = File::open(content_path)?;
-            copy(&mut BufReader::new(file), stream)?;
+            chunked::write_last_chunk(&self.trailers(), stream).await?;
+        } else {
+            self.send_content(stream).await?;
         }
-        if self.status.is_error() && content_missing {
-            stream.write(self.status.as_reason_statement().as_bytes())?;
+
+        stream.flush().await?;
+        Ok(())
+    }
+
+    // writes the message body only, framing is left to the caller
+    pub async fn send_content<W>(&mut self, stream: &mut W) -> Result<(), Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        // the headers still describe the content a GET would have gotten, Content-Length included
+        if self.meta_data.is_head_request {
+            return Ok(());
         }
 
-        stream.flush()?;
+        match &mut self.content {
+            Some(HttpMessageContent::Bytes(bytes)) => stream.write_all(bytes).await?,
+            Some(HttpMessageContent::File { file, offset, length }) => match &self.partial_content {
+                Some(partial_content) => partial_content.send(file, stream).await?,
+                None => {
+                    file.seek(SeekFrom::Start(*offset)).await?;
+                    io::copy(&mut (&mut *file).take(*length), stream).await?;
+                }
+            },
+            Some(HttpMessageContent::Stream(content)) => {
+                io::copy(content, stream).await?;
+            }
+            None => {}
+        }
         Ok(())
     }
 
+    // only streamed content has trailers, they are lost on HTTP/1.0 as it has no place for them,
+    // invalid ones are left out on every protocol
+    pub fn trailers(&mut self) -> Vec<HttpHeader> {
+        let trailers = match &mut self.content {
+            Some(HttpMessageContent::Stream(content)) => content.trailers(),
+            _ => return vec![],
+        };
+        trailers
+            .into_iter()
+            .filter(|trailer| configuration::is_valid_header(&trailer.field_name, &trailer.field_value))
+            .collect()
+    }
+
+    fn is_chunked(&self) -> bool {
+        let is_http_1_1 = self.meta_data.http_version.major == 1 && self.meta_data.http_version.minor >= 1;
+        is_http_1_1 && matches!(self.content, Some(HttpMessageContent::Stream(_)))
+    }
+
+    pub fn keeps_connection_alive(&self) -> bool {
+        self.meta_data.keep_alive
+    }
+
+    pub fn status(&self) -> &HttpStatus {
+        &self.status
+    }
+
+    pub fn headers(&self) -> &[HttpHeader] {
+        &self.headers
+    }
+
+    pub fn add_header(&mut self, header: HttpHeader) {
+        self.headers.push(header);
+    }
+
     fn generate_error_response(
         status: HttpStatus,
         meta_data: HttpConnectionMetaData,
         config: &Config,
     ) -> HttpResponse {
-        let len;
-        let content;
-        if status == HttpStatus::FileNotFound {
-            match Self::get_file_length(&config.file_not_found_path) {
-                Some(file_len) => {
-                    len = file_len;
-                    // TODO: remove clone as soon as config is migrated
-                    content = Some(Box::from(config.file_not_found_path.clone()));
-                }
-                None => {
-                    // TODO remove duplicate code (see else block below)
-                    len = status.get_reason_statement_len();
-                    content = None;
-                }
-            }
+        // the reason statement is sent instead, if there is no custom 404 page
+        let not_found_file = if status == HttpStatus::FileNotFound {
+            Self::open_file(&config.file_not_found_path)
         } else {
-            len = status.get_reason_statement_len();
-            content = None;
+            None
+        };
+
+        match not_found_file {
+            Some((file, file_meta_data)) => {
+                let content_type = mime::get_content_type(&config.file_not_found_path, config);
+                let content = HttpMessageContent::from_file(file, 0, file_meta_data.len());
+                Self::with_content(status, meta_data, content_type, content)
+            }
+            None => Self::generate_plain_response(status, meta_data),
         }
-        let headers = Self::generate_response_headers(len);
+    }
+
+    // directories can be opened as well, but can't be read like a file
+    fn open_file(path: &Path) -> Option<(fs::File, fs::Metadata)> {
+        Self::with_file_meta_data(fs::File::open(path).ok()?)
+    }
+
+    fn with_file_meta_data(file: fs::File) -> Option<(fs::File, fs::Metadata)> {
+        let file_meta_data = file.metadata().ok()?;
+        Some((file, file_meta_data)).filter(|(_, file_meta_data)| file_meta_data.is_file())
+    }
+
+    fn generate_redirect_response(
+        status_code: u16,
+        location: String,
+        meta_data: HttpConnectionMetaData,
+    ) -> HttpResponse {
+        let status = HttpStatus::from_redirect_code(status_code).unwrap_or(HttpStatus::MovedPermanently);
+        let mut headers = Self::generate_response_headers(0, &meta_data);
+        headers.push(HttpHeader {
+            field_name: String::from("Location"),
+            field_value: location,
+        });
         HttpResponse {
             status,
             meta_data,
             headers,
-            content,
+            content: None,
+            partial_content: None,
+        }
+    }
+
+    // a 304 carries no content, but the validators so caches can update their stored response
+    fn generate_not_modified_response(
+        meta_data: HttpConnectionMetaData,
+        validators: &Validators,
+    ) -> HttpResponse {
+        let mut headers = Self::generate_connection_headers(&meta_data);
+        headers.extend(validators.generate_headers());
+        HttpResponse {
+            status: HttpStatus::NotModified,
+            meta_data,
+            headers,
+            content: None,
+            partial_content: None,
         }
     }
 
@@ -181,14 +620,11 @@ impl HttpResponse {
         path: &str,
         serve_path: &PathBuf,
         index_file_name: &str,
-    ) -> Result<(HttpMessageContent, u64), HttpStatus> {
+        accept_encoding: Option<&str>,
+    ) -> Result<(Box<Path>, u64, EncodingSelection), HttpStatus> {
         let mut file_path = serve_path.clone();
 
-        let path = if path.starts_with("/") {
-            &path[1..]
-        } else {
-            &path
-        };
+        let path = path.strip_prefix('/').unwrap_or(path);
 
         file_path.push(path);
 
@@ -205,11 +641,35 @@ impl HttpResponse {
         if !file_path.starts_with(serve_path) {
             return Err(HttpStatus::FileNotFound);
         }
+        if Self::get_file_length(&file_path).is_none() {
+            return Err(HttpStatus::FileNotFound);
+        }
+
+        let (file_path, encoding_selection) =
+            encoding::select_precompressed_file(&file_path, serve_path, accept_encoding);
         let len = match Self::get_file_length(&file_path) {
             Some(len) => len,
             None => return Err(HttpStatus::FileNotFound),
         };
-        Ok((Some(Box::from(file_path.as_path())), len))
+        Ok((Box::from(file_path.as_path()), len, encoding_selection))
+    }
+
+    fn get_request_path(request_target: &HttpRequestTarget) -> Option<&str> {
+        match request_target {
+            HttpRequestTarget::OriginForm { path, .. }
+            | HttpRequestTarget::AbsoluteForm {
+                path: Some(path), ..
+            } => Some(path),
+            HttpRequestTarget::AbsoluteForm { path: None, .. } => Some("/"),
+            _ => None,
+        }
+    }
+
+    fn get_request_query(request_target: &HttpRequestTarget) -> Option<&str> {
+        match request_target {
+            HttpRequestTarget::OriginForm { query, .. } | HttpRequestTarget::AbsoluteForm { query, .. } => query.as_deref(),
+            _ => None,
+        }
     }
 
     // Can also be used as a file existance check
@@ -223,29 +683,98 @@ impl HttpResponse {
     fn generate_status_line(&self) -> String {
         format!(
             "{} {} {}",
-            self.meta_data.http_version.to_string(),
+            self.meta_data.http_version,
             self.status.as_code(),
             self.status.as_reason_statement(),
         )
     }
 
-    fn generate_response_headers(content_length: u64) -> Vec<HttpHeader> {
-        let mut headers = vec![];
-        headers.push(HttpHeader {
+    fn generate_response_headers(content_length: u64, meta_data: &HttpConnectionMetaData) -> Vec<HttpHeader> {
+        let mut headers = vec![HttpHeader {
             field_name: String::from("Content-Length"),
             field_value: content_length.to_string(),
-        });
+        }];
+        headers.extend(Self::generate_connection_headers(meta_data));
+        headers
+    }
+
+    fn generate_connection_headers(meta_data: &HttpConnectionMetaData) -> Vec<HttpHeader> {
+        let mut headers = vec![];
+
+        // HTTP/1.1 connections are persistent by default, HTTP/1.0 ones have to opt in
+        let is_http_1_0 = meta_data.http_version.major == 1 && meta_data.http_version.minor == 0;
+        if !meta_data.keep_alive {
+            headers.push(HttpHeader {
+                field_name: String::from("Connection"),
+                field_value: String::from("close"),
+            });
+        } else if is_http_1_0 {
+            headers.push(HttpHeader {
+                field_name: String::from("Connection"),
+                field_value: String::from("keep-alive"),
+            });
+        }
         headers
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::env;
+    use std::pin::Pin;
+    use std::{env, fs};
+    use std::task::{Context, Poll};
+
+    use bytes::Bytes;
+    use tokio::io::{self, AsyncRead, ReadBuf};
 
-    use crate::http::request::{HttpConnectionMetaData, HttpHeader, HttpVersion};
-    use crate::http::response::HttpStatus;
-    use crate::http::HttpResponse;
+    use crate::configuration::{Config, HeaderRule, Redirect, Route};
+    use crate::http::request::{HttpConnectionMetaData, HttpHeader, HttpParsingError, HttpVersion};
+    use crate::http::response::{HttpMessageContent, HttpStatus};
+    use crate::http::{ContentStream, HttpRequest, HttpResponse};
+
+    struct GeneratedContent {
+        content: &'static [u8],
+    }
+
+    impl AsyncRead for GeneratedContent {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.get_mut().content).poll_read(cx, buf)
+        }
+    }
+
+    impl ContentStream for GeneratedContent {
+        // only the valid trailer is sent
+        fn trailers(&mut self) -> Vec<HttpHeader> {
+            vec![
+                header("Checksum", "abc"),
+                header("Bad Name", "abc"),
+                header("X-Injected", "abc\r\nSet-Cookie: id=1"),
+            ]
+        }
+    }
+
+    async fn send_streamed(http_version: HttpVersion) -> (String, bool) {
+        let meta_data = HttpConnectionMetaData { http_version, keep_alive: true, is_head_request: false };
+        let content = Box::new(GeneratedContent { content: b"generated" });
+        let content = HttpMessageContent::Stream(content);
+        let mut response = HttpResponse::with_content(HttpStatus::Okay, meta_data, String::from("text/plain"), content);
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        (String::from_utf8(sent).unwrap(), response.keeps_connection_alive())
+    }
+
+    async fn response_for(raw_request: &str, config: &Config) -> HttpResponse {
+        let request = HttpRequest::new(&mut raw_request.as_bytes(), config).await.unwrap();
+        HttpResponse::new(request, config)
+    }
+
+    fn header(field_name: &str, field_value: &str) -> HttpHeader {
+        HttpHeader {
+            field_name: String::from(field_name),
+            field_value: String::from(field_value),
+        }
+    }
 
     #[test]
     fn request_translates_to_correct_file_path() {
@@ -257,10 +786,10 @@ mod tests {
         expected.push("example");
         expected.push(index_file_name);
 
-        let expected = Some(Box::from(expected.as_path()));
+        let expected = expected.into_boxed_path();
 
         let result =
-            HttpResponse::pre_generate_message_content(html_path, &serve_path, index_file_name)
+            HttpResponse::pre_generate_message_content(html_path, &serve_path, index_file_name, None)
                 .unwrap()
                 .0;
         assert_eq!(result, expected);
@@ -275,7 +804,7 @@ mod tests {
         let expected = Err(HttpStatus::FileNotFound);
 
         let result =
-            HttpResponse::pre_generate_message_content(html_path, &serve_path, index_file_name);
+            HttpResponse::pre_generate_message_content(html_path, &serve_path, index_file_name, None);
         assert_eq!(result, expected);
     }
 
@@ -288,7 +817,7 @@ mod tests {
         let expected = Err(HttpStatus::FileNotFound);
 
         let result =
-            HttpResponse::pre_generate_message_content(html_path, &serve_path, index_file_name);
+            HttpResponse::pre_generate_message_content(html_path, &serve_path, index_file_name, None);
         assert_eq!(result, expected);
     }
 
@@ -301,9 +830,12 @@ mod tests {
             status: status_code,
             meta_data: HttpConnectionMetaData {
                 http_version: version,
+                keep_alive: true,
+                is_head_request: false,
             },
             headers: vec![],
             content: None,
+            partial_content: None,
         };
 
         let expected = String::from("HTTP/1.1 200 OK");
@@ -314,8 +846,13 @@ mod tests {
 
     #[test]
     fn generates_valid_headers() {
-        let result = HttpResponse::generate_response_headers(123);
-        assert!(result.len() > 0);
+        let meta_data = HttpConnectionMetaData {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            keep_alive: true,
+            is_head_request: false,
+        };
+        let result = HttpResponse::generate_response_headers(123, &meta_data);
+        assert!(!result.is_empty());
         let mut content_length_header = &HttpHeader {
             field_name: String::new(),
             field_value: String::new(),
@@ -334,15 +871,190 @@ mod tests {
         );
     }
 
+    #[test]
+    fn closing_connection_is_announced() {
+        let meta_data = HttpConnectionMetaData {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            keep_alive: false,
+            is_head_request: false,
+        };
+        let result = HttpResponse::generate_response_headers(0, &meta_data);
+        assert!(result.contains(&HttpHeader {
+            field_name: "Connection".to_string(),
+            field_value: "close".to_string(),
+        }));
+    }
+
+    #[tokio::test]
+    async fn redirects_before_looking_up_files() {
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.redirects.push(Redirect {
+            from: String::from("/old/*"),
+            to: String::from("/example/*"),
+            status: 308,
+        });
+
+        let response = response_for("GET /old/missing.html?lang=en HTTP/1.1\r\n\r\n", &config).await;
+
+        assert_eq!(response.status, HttpStatus::PermanentRedirect);
+        assert!(response.headers.contains(&header("Location", "/example/missing.html?lang=en")));
+        assert!(response.headers.contains(&header("Content-Length", "0")));
+    }
+
+    #[tokio::test]
+    async fn routes_and_header_rules_apply_to_matching_paths() {
+        let example_path = env::current_dir().unwrap().join("example").canonicalize().unwrap();
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.routes.push(Route {
+            path: String::from("/site"),
+            serve_path: example_path.clone(),
+        });
+        config.header_rules.push(HeaderRule {
+            path: String::from("/site"),
+            headers: vec![(String::from("Cache-Control"), String::from("max-age=60"))],
+        });
+
+        let mut response = response_for("GET /site/ HTTP/1.1\r\n\r\n", &config).await;
+        assert_eq!(response.status, HttpStatus::Okay);
+        assert!(response.headers.contains(&header("Cache-Control", "max-age=60")));
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        assert!(sent.ends_with(&fs::read(example_path.join("index.html")).unwrap()));
+
+        let response = response_for("GET /site/missing.html HTTP/1.1\r\n\r\n", &config).await;
+        assert_eq!(response.status, HttpStatus::FileNotFound);
+        assert!(!response.headers.contains(&header("Cache-Control", "max-age=60")));
+    }
+
+    #[tokio::test]
+    async fn head_is_answered_like_get_without_content() {
+        let mut config = Config::new(&[String::from("throwscape")]).unwrap();
+        config.serve_path = env::current_dir().unwrap().join("example").canonicalize().unwrap();
+        let entity_tag = response_for("GET /index.html HTTP/1.1\r\n\r\n", &config)
+            .await
+            .headers
+            .into_iter()
+            .find(|header| header.field_name == "ETag")
+            .unwrap()
+            .field_value;
+
+        let conditional_headers = [
+            (String::new(), HttpStatus::Okay),
+            (String::from("Range: bytes=0-9\r\n"), HttpStatus::PartialContent),
+            (String::from("Range: bytes=99999-\r\n"), HttpStatus::RangeNotSatisfiable),
+            (format!("If-None-Match: {}\r\n", entity_tag), HttpStatus::NotModified),
+            (String::from("If-Match: \"other\"\r\n"), HttpStatus::PreconditionFailed),
+        ];
+        for (conditional_header, status) in conditional_headers.iter() {
+            let get = response_for(&format!("GET /index.html HTTP/1.1\r\n{}\r\n", conditional_header), &config).await;
+            let mut head = response_for(&format!("HEAD /index.html HTTP/1.1\r\n{}\r\n", conditional_header), &config).await;
+            assert_eq!(&head.status, status);
+            assert_eq!(head.status, get.status);
+            assert_eq!(head.headers, get.headers);
+
+            let mut sent = vec![];
+            head.send(&mut sent).await.unwrap();
+            assert!(sent.ends_with(b"\r\n\r\n"));
+        }
+    }
+
     #[test]
     fn http_error_is_error() {
-        assert_eq!(HttpStatus::FileNotFound.is_error(), true);
-        assert_eq!(HttpStatus::MethodNotAllowed.is_error(), true);
-        assert_eq!(HttpStatus::BadRequest.is_error(), true);
+        assert!(HttpStatus::FileNotFound.is_error());
+        assert!(HttpStatus::MethodNotAllowed.is_error());
+        assert!(HttpStatus::BadRequest.is_error());
     }
 
     #[test]
     fn http_ok_is_no_error() {
-        assert_eq!(HttpStatus::Okay.is_error(), false);
+        assert!(!HttpStatus::Okay.is_error());
+    }
+
+    #[tokio::test]
+    async fn overloaded_response_asks_to_retry() {
+        let meta_data = HttpConnectionMetaData {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            keep_alive: true,
+            is_head_request: false,
+        };
+        let mut response = HttpResponse::new_overloaded(meta_data);
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(sent.contains("Retry-After: 1\r\n"));
+        assert!(sent.ends_with("\r\n\r\nService Unavailable"));
+    }
+
+    #[tokio::test]
+    async fn internal_error_for_head_has_no_content() {
+        let meta_data = HttpConnectionMetaData {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            keep_alive: true,
+            is_head_request: true,
+        };
+        let mut response = HttpResponse::new_internal_error(meta_data);
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        assert!(sent.contains("Content-Length: 21\r\n"));
+        assert!(sent.ends_with("\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn sends_content_without_a_path() {
+        let meta_data = HttpConnectionMetaData {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            keep_alive: true,
+            is_head_request: false,
+        };
+        let content = HttpMessageContent::Bytes(Bytes::from_static(b"in memory"));
+        let mut response = HttpResponse::with_content(HttpStatus::Okay, meta_data.clone(), String::from("text/plain"), content);
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.contains("Content-Length: 9\r\n"));
+        assert!(sent.ends_with("\r\n\r\nin memory"));
+
+        let file = fs::File::open("example/index.html").unwrap();
+        let content = HttpMessageContent::from_file(file, 2, 13);
+        let mut response = HttpResponse::with_content(HttpStatus::Okay, meta_data, String::from("text/html"), content);
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.contains("Content-Length: 13\r\n"));
+        assert!(sent.ends_with(&fs::read_to_string("example/index.html").unwrap()[2..15]));
+    }
+
+    #[tokio::test]
+    async fn streamed_content_is_chunked_or_close_delimited() {
+        let (sent, keep_alive) = send_streamed(HttpVersion { major: 1, minor: 1 }).await;
+        assert!(sent.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!sent.contains("Content-Length"));
+        assert!(sent.ends_with("\r\n\r\n9\r\ngenerated\r\n0\r\nChecksum: abc\r\n\r\n"));
+        assert!(keep_alive);
+
+        // without chunks, closing the connection is the only way to end the content
+        let (sent, keep_alive) = send_streamed(HttpVersion { major: 1, minor: 0 }).await;
+        assert!(sent.starts_with("HTTP/1.0 200 OK\r\nConnection: close\r\n"));
+        assert!(!sent.contains("Transfer-Encoding"));
+        assert!(sent.ends_with("\r\n\r\ngenerated"));
+        assert!(!keep_alive);
+    }
+
+    #[tokio::test]
+    async fn parsing_error_is_answered_before_closing() {
+        let mut response = HttpResponse::new_parsing_error(&HttpParsingError::HeadersTooLarge).unwrap();
+
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert!(sent.contains("Connection: close\r\n"));
+
+        assert_eq!(HttpResponse::new_parsing_error(&HttpParsingError::TcpIssue()), None);
     }
 }
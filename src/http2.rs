@@ -0,0 +1,616 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use h2::server::SendResponse;
+use h2::{Reason, RecvStream, SendStream};
+use http::{HeaderMap, HeaderName, HeaderValue, Request, Response};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, BufReader, ReadBuf, ReadHalf, WriteHalf};
+use tokio::time;
+
+use crate::configuration::Config;
+use crate::http3;
+use crate::http::{HttpHeader, HttpMethod, HttpRequest, HttpRequestTarget, HttpResponse, HttpVersion};
+use crate::scheduler::ThreadPool;
+use crate::shutdown::ShutdownSignal;
+
+pub const CONNECTION_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+pub const SWITCHING_PROTOCOLS_RESPONSE: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+const MAX_CONCURRENT_STREAMS: u32 = 128;
+// frame size every peer has to accept before settings are exchanged (rfc9113 section 4.2)
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+const FRAME_HEADER_LEN: usize = 9;
+const FRAME_TYPE_HEADERS: u8 = 0x1;
+const FRAME_TYPE_SETTINGS: u8 = 0x4;
+const FLAG_END_STREAM: u8 = 0x1;
+const FLAG_END_HEADERS: u8 = 0x4;
+
+// fields that only describe a single HTTP/1 connection and are invalid in HTTP/2 (rfc9113 section 8.2.2)
+const CONNECTION_SPECIFIC_FIELDS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+    "http2-settings",
+    "te",
+    "host",
+];
+
+// the preface can arrive in pieces, so reading goes on until the bytes either stop matching it or hold all of it,
+// never past its end, HTTP/1 requests usually differ after their first read already
+pub async fn read_preface<S>(stream: &mut S) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut read_bytes = vec![0; CONNECTION_PREFACE.len()];
+    let mut len = 0;
+    while len < CONNECTION_PREFACE.len() && read_bytes[..len] == CONNECTION_PREFACE[..len] {
+        let read_len = stream.read(&mut read_bytes[len..]).await?;
+        // closed before anything could be told apart, left to the HTTP/1 parser to answer
+        if read_len == 0 {
+            break;
+        }
+        len += read_len;
+    }
+    read_bytes.truncate(len);
+    Ok(read_bytes)
+}
+
+pub fn is_connection_preface(read_bytes: &[u8]) -> bool {
+    read_bytes == CONNECTION_PREFACE
+}
+
+pub async fn serve_connection<S>(
+    stream: S,
+    alt_svc_port: Option<u16>,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+    mut shutdown: ShutdownSignal,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake = h2::server::Builder::new()
+        .max_concurrent_streams(MAX_CONCURRENT_STREAMS)
+        .handshake::<_, Bytes>(stream);
+
+    let mut connection = match time::timeout(config.timeout, handshake).await {
+        Ok(Ok(connection)) => connection,
+        Ok(Err(err)) => {
+            if config.is_in_debug_mode {
+                eprintln!("HTTP/2 Error: {}", err);
+            }
+            return;
+        }
+        Err(_) => return,
+    };
+
+    let mut is_shutting_down = false;
+    loop {
+        // once idle for the keep alive timeout or when the server stops, the client is sent away while running streams still finish
+        let next_stream = if is_shutting_down {
+            connection.accept().await
+        } else {
+            tokio::select! {
+                next_stream = time::timeout(config.keep_alive_timeout, connection.accept()) => match next_stream {
+                    Ok(next_stream) => next_stream,
+                    Err(_) => {
+                        connection.graceful_shutdown();
+                        is_shutting_down = true;
+                        continue;
+                    }
+                },
+                _ = shutdown.triggered() => {
+                    connection.graceful_shutdown();
+                    is_shutting_down = true;
+                    continue;
+                }
+            }
+        };
+
+        match next_stream {
+            Some(Ok((request, respond))) => {
+                let config = Arc::clone(&config);
+                let pool = Arc::clone(&pool);
+                tokio::spawn(async move { handle_stream(request, respond, alt_svc_port, config, pool).await });
+            }
+            Some(Err(err)) => {
+                if config.is_in_debug_mode {
+                    eprintln!("HTTP/2 Error: {}", err);
+                }
+                return;
+            }
+            None => return,
+        }
+    }
+}
+
+// the upgraded request becomes stream 1, so it's replayed to the server as if the client had sent it (rfc7540 section 3.2)
+pub async fn serve_upgraded_connection<S>(
+    mut stream: S,
+    upgrade_frame: Vec<u8>,
+    alt_svc_port: Option<u16>,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+    shutdown: ShutdownSignal,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let client_preface = match time::timeout(config.timeout, read_client_preface(&mut stream)).await {
+        Ok(Ok(client_preface)) => client_preface,
+        Ok(Err(err)) => {
+            if config.is_in_debug_mode {
+                eprintln!("HTTP/2 Error: {}", err);
+            }
+            return;
+        }
+        Err(_) => return,
+    };
+
+    let mut prefix = client_preface;
+    prefix.extend(upgrade_frame);
+    serve_connection(PrefixedStream::new(prefix, stream), alt_svc_port, config, pool, shutdown).await;
+}
+
+// the preface has to be followed by a settings frame, which is the only frame allowed before stream 1
+async fn read_client_preface<S>(stream: &mut S) -> io::Result<Vec<u8>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut client_preface = vec![0; CONNECTION_PREFACE.len() + FRAME_HEADER_LEN];
+    stream.read_exact(&mut client_preface).await?;
+
+    let frame_header = &client_preface[CONNECTION_PREFACE.len()..];
+    let payload_len = u32::from_be_bytes([0, frame_header[0], frame_header[1], frame_header[2]]) as usize;
+    if !client_preface.starts_with(CONNECTION_PREFACE)
+        || frame_header[3] != FRAME_TYPE_SETTINGS
+        || payload_len > DEFAULT_MAX_FRAME_SIZE
+    {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid connection preface"));
+    }
+
+    let header_len = client_preface.len();
+    client_preface.resize(header_len + payload_len, 0);
+    stream.read_exact(&mut client_preface[header_len..]).await?;
+    Ok(client_preface)
+}
+
+// returns the request as HTTP/2 headers frame if the client asked for an upgrade that can be honored
+pub fn prepare_upgrade(request: &HttpRequest) -> Option<Vec<u8>> {
+    let has_token = |field_name: &str, token: &str| {
+        request
+            .headers
+            .iter()
+            .filter(|header| header.field_name.eq_ignore_ascii_case(field_name))
+            .flat_map(|header| header.field_value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+
+    let is_http_1_1 = request.meta_data.http_version == HttpVersion { major: 1, minor: 1 };
+    // request bodies would have to be forwarded as well, these requests are just answered with HTTP/1.1
+    let is_upgradeable = is_http_1_1
+        && request.meta_data.keep_alive
+        && (request.method == HttpMethod::GET || request.method == HttpMethod::HEAD)
+        && has_token("Upgrade", "h2c")
+        && has_token("Connection", "Upgrade")
+        && has_token("Connection", "HTTP2-Settings")
+        && request.get_header("HTTP2-Settings").is_some();
+    if !is_upgradeable {
+        return None;
+    }
+
+    let target = match &request.request_target {
+        HttpRequestTarget::OriginForm { path, query: Some(query) } => format!("{}?{}", path, query),
+        HttpRequestTarget::OriginForm { path, query: None } => path.clone(),
+        _ => return None,
+    };
+
+    let mut header_block = vec![];
+    let method = format!("{:?}", request.method);
+    encode_header_field(&mut header_block, ":method", &method);
+    encode_header_field(&mut header_block, ":scheme", "http");
+    encode_header_field(&mut header_block, ":path", &target);
+    if let Some(host) = request.get_header("Host") {
+        encode_header_field(&mut header_block, ":authority", host);
+    }
+    for header in request.headers.iter() {
+        let field_name = header.field_name.to_ascii_lowercase();
+        if !CONNECTION_SPECIFIC_FIELDS.contains(&field_name.as_str()) {
+            encode_header_field(&mut header_block, &field_name, header.field_value.trim());
+        }
+    }
+
+    if header_block.len() > DEFAULT_MAX_FRAME_SIZE {
+        return None;
+    }
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + header_block.len());
+    frame.extend_from_slice(&(header_block.len() as u32).to_be_bytes()[1..]);
+    frame.push(FRAME_TYPE_HEADERS);
+    frame.push(FLAG_END_STREAM | FLAG_END_HEADERS);
+    frame.extend_from_slice(&1u32.to_be_bytes());
+    frame.extend(header_block);
+    Some(frame)
+}
+
+// literal header field without indexing and huffman coding, so no compression state is involved (rfc7541 section 6.2.2)
+fn encode_header_field(header_block: &mut Vec<u8>, field_name: &str, field_value: &str) {
+    header_block.push(0x00);
+    encode_string(header_block, field_name.as_bytes());
+    encode_string(header_block, field_value.as_bytes());
+}
+
+fn encode_string(header_block: &mut Vec<u8>, string: &[u8]) {
+    // string lengths use a 7 bit prefix, continued in 7 bit groups (rfc7541 section 5.1)
+    const PREFIX_MAX: usize = 0x7f;
+
+    if string.len() < PREFIX_MAX {
+        header_block.push(string.len() as u8);
+    } else {
+        header_block.push(PREFIX_MAX as u8);
+        let mut remainder = string.len() - PREFIX_MAX;
+        while remainder >= 0x80 {
+            header_block.push((remainder & 0x7f) as u8 | 0x80);
+            remainder >>= 7;
+        }
+        header_block.push(remainder as u8);
+    }
+    header_block.extend_from_slice(string);
+}
+
+async fn handle_stream(
+    request: Request<RecvStream>,
+    mut respond: SendResponse<Bytes>,
+    alt_svc_port: Option<u16>,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+) {
+    let request = match convert_request(&request, HttpVersion { major: 2, minor: 0 }) {
+        Some(request) => request,
+        None => {
+            respond.send_reset(Reason::PROTOCOL_ERROR);
+            return;
+        }
+    };
+
+    if config.is_in_debug_mode {
+        println!("{}", request);
+    }
+
+    let mut response = crate::generate_response(request, &config, &pool).await;
+    if let Some(alt_svc_port) = alt_svc_port {
+        response.add_header(http3::generate_alt_svc_header(alt_svc_port));
+    }
+
+    let head = match convert_response_head(&response) {
+        Some(head) => head,
+        None => {
+            respond.send_reset(Reason::INTERNAL_ERROR);
+            return;
+        }
+    };
+
+    let send_stream = match respond.send_response(head, false) {
+        Ok(send_stream) => send_stream,
+        Err(_) => return,
+    };
+
+    let mut writer = SendStreamWriter { send_stream };
+    let result = match response.send_content(&mut writer).await {
+        Ok(()) => match convert_trailers(&response.trailers()) {
+            Some(trailers) => writer.send_stream.send_trailers(trailers).map_err(io::Error::other),
+            None => writer.send_stream.send_data(Bytes::new(), true).map_err(io::Error::other),
+        },
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        if config.is_in_debug_mode {
+            eprintln!("Response Error: {}", err);
+        }
+        writer.send_stream.send_reset(Reason::INTERNAL_ERROR);
+    }
+}
+
+// shared with HTTP/3, which uses the same request and response representation
+pub fn convert_request<T>(request: &Request<T>, http_version: HttpVersion) -> Option<HttpRequest> {
+    let target = request.uri().path_and_query().map(|target| target.as_str()).unwrap_or("");
+    // a value HTTP/1.1 would reject fails the whole request instead of leaving out the field
+    let headers = request
+        .headers()
+        .iter()
+        .map(|(field_name, field_value)| {
+            Some(HttpHeader {
+                field_name: String::from(field_name.as_str()),
+                field_value: String::from(field_value.to_str().ok()?),
+            })
+        })
+        .collect::<Option<Vec<HttpHeader>>>()?;
+
+    HttpRequest::from_parts(request.method().as_str(), target, http_version, headers).ok()
+}
+
+pub fn convert_response_head(response: &HttpResponse) -> Option<Response<()>> {
+    let mut head = Response::builder().status(response.status().as_code() as u16);
+    for header in response.headers() {
+        if !CONNECTION_SPECIFIC_FIELDS.contains(&header.field_name.to_ascii_lowercase().as_str()) {
+            head = head.header(header.field_name.as_str(), header.field_value.as_str());
+        }
+    }
+    head.body(()).ok()
+}
+
+// None if there are no trailers, the stream is ended with an empty data frame then,
+// trailers HTTP/2 has no place for are left out on their own, just like in the response head
+pub fn convert_trailers(trailers: &[HttpHeader]) -> Option<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for trailer in trailers {
+        if CONNECTION_SPECIFIC_FIELDS.contains(&trailer.field_name.to_ascii_lowercase().as_str()) {
+            continue;
+        }
+        let field_name = HeaderName::from_bytes(trailer.field_name.as_bytes());
+        let field_value = HeaderValue::from_str(&trailer.field_value);
+        if let (Ok(field_name), Ok(field_value)) = (field_name, field_value) {
+            header_map.append(field_name, field_value);
+        }
+    }
+    Some(header_map).filter(|header_map| !header_map.is_empty())
+}
+
+// bridges the response body onto a stream, only writing as much as the flow control window allows
+struct SendStreamWriter {
+    send_stream: SendStream<Bytes>,
+}
+
+impl AsyncWrite for SendStreamWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let send_stream = &mut self.get_mut().send_stream;
+        send_stream.reserve_capacity(buf.len());
+        loop {
+            let capacity = send_stream.capacity();
+            if capacity > 0 {
+                let len = capacity.min(buf.len());
+                return Poll::Ready(
+                    send_stream
+                        .send_data(Bytes::copy_from_slice(&buf[..len]), false)
+                        .map(|_| len)
+                        .map_err(io::Error::other),
+                );
+            }
+
+            match send_stream.poll_capacity(cx) {
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(io::Error::other(err))),
+                Poll::Ready(None) => return Poll::Ready(Err(io::Error::from(io::ErrorKind::BrokenPipe))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// replays bytes that were already read from a stream, ex. while looking for the HTTP/2 preface
+pub struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> PrefixedStream<S> {
+        PrefixedStream {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+}
+
+// puts a split connection back together without losing what the reader had buffered already
+pub fn reunite<S>(buffered_reader: BufReader<ReadHalf<S>>, writer: WriteHalf<S>) -> PrefixedStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let buffered = buffered_reader.buffer().to_vec();
+    PrefixedStream::new(buffered, buffered_reader.into_inner().unsplit(writer))
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefixedStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.position < this.prefix.len() {
+            let remaining = &this.prefix[this.position..];
+            let len = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..len]);
+            this.position += len;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use http::Request;
+    use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+    use tokio::time;
+
+    use crate::configuration::Config;
+    use crate::http::{HttpHeader, HttpRequest, HttpVersion};
+    use crate::scheduler::ThreadPool;
+    use crate::shutdown::Shutdown;
+
+    use super::{CONNECTION_PREFACE, FRAME_TYPE_HEADERS};
+
+    fn config() -> Arc<Config> {
+        Arc::new(Config::new(&[String::from("throwscape")]).unwrap())
+    }
+
+    async fn request(raw_request: &str) -> HttpRequest {
+        HttpRequest::new(&mut raw_request.as_bytes(), &config()).await.unwrap()
+    }
+
+    #[test]
+    fn leaves_out_connection_specific_trailers_only() {
+        let trailers = [
+            HttpHeader { field_name: String::from("Checksum"), field_value: String::from("abc") },
+            HttpHeader { field_name: String::from("Connection"), field_value: String::from("close") },
+        ];
+
+        let header_map = super::convert_trailers(&trailers).unwrap();
+        assert_eq!(header_map.len(), 1);
+        assert_eq!(header_map["checksum"], "abc");
+        assert_eq!(super::convert_trailers(&trailers[1..]), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_header_values_that_are_not_text() {
+        let raw_request: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Language: caf\xe9\r\n\r\n";
+        assert!(HttpRequest::new(&mut &raw_request[..], &config()).await.is_err());
+
+        let request = Request::get("http://localhost/")
+            .header("Accept-Language", http::HeaderValue::from_bytes(b"caf\xe9").unwrap())
+            .body(())
+            .unwrap();
+        assert!(super::convert_request(&request, HttpVersion { major: 2, minor: 0 }).is_none());
+    }
+
+    #[tokio::test]
+    async fn reads_preface_arriving_in_pieces() {
+        let (mut client_io, mut server_io) = io::duplex(64);
+        let client = tokio::spawn(async move {
+            for piece in CONNECTION_PREFACE.chunks(3) {
+                client_io.write_all(piece).await.unwrap();
+                time::sleep(Duration::from_millis(5)).await;
+            }
+            client_io
+        });
+        let read_bytes = super::read_preface(&mut server_io).await.unwrap();
+        assert!(super::is_connection_preface(&read_bytes));
+        client.await.unwrap();
+
+        let mut raw_request: &[u8] = b"PUT / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let read_bytes = super::read_preface(&mut raw_request).await.unwrap();
+        assert!(!super::is_connection_preface(&read_bytes));
+        assert_eq!(read_bytes, b"PUT / HTTP/1.1\r\nHost: lo");
+
+        let mut closed_early: &[u8] = b"PRI";
+        let read_bytes = super::read_preface(&mut closed_early).await.unwrap();
+        assert!(!super::is_connection_preface(&read_bytes));
+        assert_eq!(read_bytes, b"PRI");
+    }
+
+    #[tokio::test]
+    async fn only_honors_complete_upgrade_requests() {
+        let upgrade = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAoAAAAAIAAAAA\r\n\r\n";
+        let missing_settings = "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+        let frame = super::prepare_upgrade(&request(upgrade).await).unwrap();
+        assert_eq!(frame[3], FRAME_TYPE_HEADERS);
+        assert_eq!(&frame[5..9], &[0, 0, 0, 1]);
+        assert_eq!(super::prepare_upgrade(&request(missing_settings).await), None);
+    }
+
+    #[test]
+    fn encodes_long_strings_with_continuation() {
+        let mut header_block = vec![];
+        super::encode_string(&mut header_block, &[b'a'; 300]);
+        assert_eq!(&header_block[..3], &[0x7f, 0xad, 0x01]);
+        assert_eq!(header_block.len(), 303);
+    }
+
+    #[tokio::test]
+    async fn serves_files_over_http2() {
+        let (client_io, server_io) = io::duplex(64 * 1024);
+        let shutdown = Shutdown::new();
+        tokio::spawn(super::serve_connection(server_io, None, config(), Arc::new(ThreadPool::new(2, 16)), shutdown.signal()));
+
+        let (client, connection) = h2::client::handshake(client_io).await.unwrap();
+        tokio::spawn(connection);
+        let mut client = client.ready().await.unwrap();
+
+        let request = Request::get("http://localhost/example/index.html").body(()).unwrap();
+        let (response, _) = client.send_request(request, true).unwrap();
+        let missing_request = Request::get("http://localhost/example/missing.html").body(()).unwrap();
+        let (missing_response, _) = client.send_request(missing_request, true).unwrap();
+
+        let mut body = response.await.unwrap().into_body();
+        let mut content = vec![];
+        while let Some(chunk) = body.data().await {
+            let chunk: Bytes = chunk.unwrap();
+            body.flow_control().release_capacity(chunk.len()).unwrap();
+            content.extend_from_slice(&chunk);
+        }
+        assert_eq!(content, std::fs::read("example/index.html").unwrap());
+        assert_eq!(missing_response.await.unwrap().status(), 404);
+    }
+
+    #[tokio::test]
+    async fn answers_upgraded_request_on_stream_1() {
+        let raw_request = "GET /example/index.html HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAoAAAAAIAAAAA\r\n\r\n";
+        let upgrade_frame = super::prepare_upgrade(&request(raw_request).await).unwrap();
+
+        let (mut client_io, server_io) = io::duplex(64 * 1024);
+        let shutdown = Shutdown::new();
+        tokio::spawn(super::serve_upgraded_connection(
+            server_io,
+            upgrade_frame,
+            None,
+            config(),
+            Arc::new(ThreadPool::new(2, 16)),
+            shutdown.signal(),
+        ));
+
+        client_io.write_all(CONNECTION_PREFACE).await.unwrap();
+        client_io.write_all(&[0, 0, 0, 0x4, 0, 0, 0, 0, 0]).await.unwrap();
+
+        // skip server settings and acknowledgements until the response headers arrive
+        loop {
+            let mut frame_header = [0; 9];
+            client_io.read_exact(&mut frame_header).await.unwrap();
+            let payload_len = u32::from_be_bytes([0, frame_header[0], frame_header[1], frame_header[2]]) as usize;
+            let mut payload = vec![0; payload_len];
+            client_io.read_exact(&mut payload).await.unwrap();
+
+            if frame_header[3] == FRAME_TYPE_HEADERS {
+                assert_eq!(&frame_header[5..9], &[0, 0, 0, 1]);
+                // ":status: 200" is entry 8 of the static table
+                assert_eq!(payload[0], 0x88);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::convert::TryFrom;
+use std::io;
+use std::net::UdpSocket;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use h3::server::RequestResolver;
+use quinn::crypto::rustls::QuicServerConfig;
+use quinn::{Endpoint, EndpointConfig, IdleTimeout, TokioRuntime, TransportConfig};
+use tokio::io::AsyncReadExt;
+use tokio::task::JoinSet;
+use tokio::time;
+
+use crate::configuration::Config;
+use crate::http::{HttpHeader, HttpVersion};
+use crate::http2;
+use crate::scheduler::ThreadPool;
+use crate::shutdown::ShutdownSignal;
+use crate::tls::{self, CertificateStore};
+
+// how long clients may remember the HTTP/3 endpoint
+const ALT_SVC_MAX_AGE: u64 = 86_400;
+const CHUNK_SIZE: usize = 16 * 1024;
+
+// advertises the QUIC listener on HTTP/1.1 and HTTP/2 responses (rfc7838)
+pub fn generate_alt_svc_header(port: u16) -> HttpHeader {
+    HttpHeader {
+        field_name: String::from("Alt-Svc"),
+        field_value: format!("h3=\":{}\"; ma={}", port, ALT_SVC_MAX_AGE),
+    }
+}
+
+pub fn create_endpoint(
+    certificate_store: &Arc<CertificateStore>,
+    socket: UdpSocket,
+    config: &Config,
+) -> Result<Endpoint, String> {
+    let tls_config = tls::create_server_config(certificate_store, vec![b"h3".to_vec()])?;
+    let quic_config = QuicServerConfig::try_from(tls_config)
+        .map_err(|err| format!("Invalid QUIC configuration: {}", err))?;
+
+    // QUIC tracks idleness itself, running transfers keep the connection busy
+    let mut transport_config = TransportConfig::default();
+    let idle_timeout = IdleTimeout::try_from(config.keep_alive_timeout)
+        .map_err(|_| String::from("Keep alive timeout is too long for QUIC"))?;
+    transport_config.max_idle_timeout(Some(idle_timeout));
+
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(quic_config));
+    server_config.transport_config(Arc::new(transport_config));
+
+    Endpoint::new(EndpointConfig::default(), Some(server_config), socket, Arc::new(TokioRuntime))
+        .map_err(|err| format!("Could not start QUIC listener: {}", err))
+}
+
+pub async fn serve(endpoint: Endpoint, config: Arc<Config>, pool: Arc<ThreadPool>, mut shutdown: ShutdownSignal) {
+    loop {
+        let incoming = tokio::select! {
+            incoming = async {
+                crate::wait_for_capacity(&config, &pool).await;
+                endpoint.accept().await
+            } => match incoming {
+                Some(incoming) => incoming,
+                None => return,
+            },
+            _ = shutdown.triggered() => {
+                // established connections keep the endpoint running until they're done
+                endpoint.set_server_config(None);
+                return;
+            }
+        };
+
+        let config = Arc::clone(&config);
+        let pool = Arc::clone(&pool);
+        let shutdown = shutdown.clone();
+
+        tokio::spawn(async move {
+            let connecting = match incoming.accept() {
+                Ok(connecting) => connecting,
+                Err(_) => return,
+            };
+            match time::timeout(config.timeout, connecting).await {
+                Ok(Ok(connection)) => handle_connection(connection, config, pool, shutdown).await,
+                Ok(Err(err)) => {
+                    if config.is_in_debug_mode {
+                        eprintln!("QUIC Error: {}", err);
+                    }
+                }
+                Err(_) => {
+                    if config.is_in_debug_mode {
+                        eprintln!("QUIC Error: handshake timed out");
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    connection: quinn::Connection,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+    mut shutdown: ShutdownSignal,
+) {
+    let mut connection = match h3::server::builder()
+        .build::<_, Bytes>(h3_quinn::Connection::new(connection))
+        .await
+    {
+        Ok(connection) => connection,
+        Err(err) => {
+            if config.is_in_debug_mode {
+                eprintln!("HTTP/3 Error: {}", err);
+            }
+            return;
+        }
+    };
+
+    // requests are tracked, so shutting down can wait for them
+    let mut requests = JoinSet::new();
+    loop {
+        let accepted = tokio::select! {
+            accepted = connection.accept() => accepted,
+            _ = shutdown.triggered() => {
+                // GOAWAY makes the client use a new connection for further requests
+                let _ = connection.shutdown(0).await;
+                break;
+            }
+        };
+
+        match accepted {
+            Ok(Some(resolver)) => {
+                let config = Arc::clone(&config);
+                let pool = Arc::clone(&pool);
+                requests.spawn(async move { handle_request(resolver, config, pool).await });
+            }
+            Ok(None) => break,
+            Err(err) => {
+                if config.is_in_debug_mode && !err.is_h3_no_error() {
+                    eprintln!("HTTP/3 Error: {}", err);
+                }
+                break;
+            }
+        }
+    }
+
+    while requests.join_next().await.is_some() {}
+}
+
+async fn handle_request(
+    resolver: RequestResolver<h3_quinn::Connection, Bytes>,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+) {
+    let (request, mut stream) = match resolver.resolve_request().await {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            if config.is_in_debug_mode {
+                eprintln!("Request Error: {}", err);
+            }
+            return;
+        }
+    };
+
+    let request = match http2::convert_request(&request, HttpVersion { major: 3, minor: 0 }) {
+        Some(request) => request,
+        None => {
+            stream.stop_stream(h3::error::Code::H3_MESSAGE_ERROR);
+            return;
+        }
+    };
+
+    if config.is_in_debug_mode {
+        println!("{}", request);
+    }
+
+    let mut response = crate::generate_response(request, &config, &pool).await;
+
+    let head = match http2::convert_response_head(&response) {
+        Some(head) => head,
+        None => {
+            stream.stop_stream(h3::error::Code::H3_INTERNAL_ERROR);
+            return;
+        }
+    };
+    if let Err(err) = stream.send_response(head).await {
+        if config.is_in_debug_mode {
+            eprintln!("Response Error: {}", err);
+        }
+        return;
+    }
+
+    // the body is written into a pipe and forwarded in chunks, as sending data on a stream is async only
+    let (mut content_writer, mut content_reader) = tokio::io::duplex(CHUNK_SIZE);
+    // the writer is moved in, so it's dropped and ends the pipe once the content is written
+    let sending_response = &mut response;
+    let send_content = async move { sending_response.send_content(&mut content_writer).await };
+    let forward_content = async {
+        let mut buffer = vec![0; CHUNK_SIZE];
+        loop {
+            let len = content_reader.read(&mut buffer).await?;
+            if len == 0 {
+                break;
+            }
+            stream
+                .send_data(Bytes::copy_from_slice(&buffer[..len]))
+                .await
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    };
+
+    let (sent, forwarded) = tokio::join!(send_content, forward_content);
+    let finish = async {
+        sent.and(forwarded)?;
+        if let Some(trailers) = http2::convert_trailers(&response.trailers()) {
+            stream.send_trailers(trailers).await.map_err(io::Error::other)?;
+        }
+        stream.finish().await.map_err(io::Error::other)
+    };
+    if let Err(err) = finish.await {
+        if config.is_in_debug_mode {
+            eprintln!("Response Error: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+    use std::fs;
+
+    use bytes::Buf;
+    use http::Request;
+    use quinn::crypto::rustls::QuicClientConfig;
+    use rustls::crypto::ring;
+    use rustls::RootCertStore;
+
+    use crate::configuration::Config;
+    use crate::scheduler::ThreadPool;
+    use crate::shutdown::Shutdown;
+    use crate::test_util::TempDirectory;
+    use crate::tls::CertificateStore;
+
+    #[test]
+    fn alt_svc_points_to_the_quic_port() {
+        let header = super::generate_alt_svc_header(8443);
+        assert_eq!(header.field_name, "Alt-Svc");
+        assert_eq!(header.field_value, "h3=\":8443\"; ma=86400");
+    }
+
+    #[tokio::test]
+    async fn serves_files_over_loopback_quic() {
+        let directory = TempDirectory::new("throwscape_http3_test");
+        let certified_key = rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+        let cert_path = directory.join("localhost.pem");
+        let key_path = directory.join("localhost.key");
+        fs::write(&cert_path, certified_key.cert.pem()).unwrap();
+        fs::write(&key_path, certified_key.key_pair.serialize_pem()).unwrap();
+
+        let args = [
+            String::from("throwscape"),
+            String::from("--tls-cert"), cert_path.to_string_lossy().to_string(),
+            String::from("--tls-key"), key_path.to_string_lossy().to_string(),
+            String::from("--http3"),
+        ];
+        let config = Arc::new(Config::new(&args).unwrap());
+        let certificate_store = Arc::new(CertificateStore::new(&config.tls_certificates).unwrap());
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let endpoint = super::create_endpoint(&certificate_store, socket, &config).unwrap();
+        let server_address = endpoint.local_addr().unwrap();
+        let shutdown = Shutdown::new();
+        tokio::spawn(super::serve(endpoint, config, Arc::new(ThreadPool::new(2, 16)), shutdown.signal()));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(certified_key.cert.der().clone()).unwrap();
+        let mut client_crypto = rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client_crypto.alpn_protocols = vec![b"h3".to_vec()];
+
+        let mut client_endpoint = quinn::Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+        client_endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(
+            QuicClientConfig::try_from(client_crypto).unwrap(),
+        )));
+        let connection = client_endpoint.connect(server_address, "localhost").unwrap().await.unwrap();
+
+        let (mut driver, mut send_request) = h3::client::new(h3_quinn::Connection::new(connection)).await.unwrap();
+        tokio::spawn(async move { std::future::poll_fn(|cx| driver.poll_close(cx)).await });
+
+        let request = Request::get("https://localhost/example/index.html").body(()).unwrap();
+        let mut stream = send_request.send_request(request).await.unwrap();
+        stream.finish().await.unwrap();
+
+        let response = stream.recv_response().await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let mut content = vec![];
+        while let Some(mut chunk) = stream.recv_data().await.unwrap() {
+            content.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+        }
+        assert_eq!(content, fs::read("example/index.html").unwrap());
+    }
+}
@@ -1,50 +1,425 @@
-use std::{net::TcpListener, sync::Arc};
+use std::{fs, sync::Arc, time::Duration};
 
-use configuration::Config;
-use http::{HttpRequest, HttpResponse};
+use configuration::{Config, ListenAddress, OverloadPolicy};
+use crate::http::{BodyReader, HttpParsingError, HttpRequest, HttpResponse};
+use tokio::{io::{self, AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader}, net::{TcpListener, UnixListener}, task::JoinSet, time};
+use tokio_rustls::TlsAcceptor;
 
-use crate::scheduler::ThreadPool;
+use crate::http2::PrefixedStream;
+use crate::listener::Listener;
+use crate::scheduler::{QueueFull, ThreadPool};
+use crate::shutdown::{Shutdown, ShutdownSignal};
 
 pub mod configuration;
 pub mod http;
+pub mod http2;
+pub mod http3;
+pub mod listener;
 pub mod scheduler;
+pub mod shutdown;
+pub mod tls;
 
-pub fn run(config: Arc<Config>, listener: TcpListener) {
-    let pool = ThreadPool::new(4);
+#[cfg(test)]
+mod test_util;
 
-    println!("Throwscape listening on port {}", config.port);
-    for stream in listener.incoming() {
-        let mut stream = match stream {
-            Ok(stream) => stream,
-            Err(_) => continue,
+// how long and how much of a broken request is still read after answering it
+const LINGER_TIMEOUT: Duration = Duration::from_secs(2);
+const LINGER_LIMIT: u64 = 64 * 1024;
+
+pub async fn run(config: Arc<Config>) -> Result<(), String> {
+    // sockets are driven by tokio, the pool only takes the blocking file system work
+    let pool = Arc::new(ThreadPool::new(config.threads, config.queue_size));
+
+    let (tls_acceptor, certificate_store) = match tls::create_acceptor(&config)? {
+        Some((tls_acceptor, certificate_store)) => (Some(tls_acceptor), Some(certificate_store)),
+        None => (None, None),
+    };
+
+    let reload_task = certificate_store.as_ref().map(|certificate_store| {
+        let certificate_store = Arc::clone(certificate_store);
+        let pool = Arc::clone(&pool);
+        let is_in_debug_mode = config.is_in_debug_mode;
+        tokio::spawn(async move {
+            let mut reload_interval = time::interval(tls::RELOAD_INTERVAL);
+            loop {
+                reload_interval.tick().await;
+                let certificate_store = Arc::clone(&certificate_store);
+                pool.handle_blocking(move || certificate_store.reload_changed_certificates(is_in_debug_mode)).await;
+            }
+        })
+    });
+
+    // everything is bound before serving, so a taken address stops the server right away
+    let shutdown = Shutdown::new();
+    let mut listeners = JoinSet::new();
+    let mut unix_socket_paths = vec![];
+    for bind_address in &config.bind_addresses {
+        let listener = match &bind_address.address {
+            ListenAddress::Tcp(address) => {
+                let is_dual_stack = bind_address.is_dual_stack(&config.bind_addresses);
+                listener::bind_tcp(*address, is_dual_stack).map(|listener| Listener::Tcp { listener, is_dual_stack })
+            }
+            ListenAddress::Unix(path) => {
+                unix_socket_paths.push(path.clone());
+                listener::bind_unix(path, config.unix_socket_mode).map(Listener::Unix)
+            }
+            ListenAddress::Inherited(fd) => listener::inherit(*fd),
+        }
+        .map_err(|err| format!("Could not bind to {}: {}", bind_address.address, err))?;
+
+        let (listener, is_dual_stack) = match listener {
+            Listener::Tcp { listener, is_dual_stack } => (listener, is_dual_stack),
+            Listener::Unix(listener) => {
+                if bind_address.is_tls {
+                    return Err(format!("Could not serve TLS on {}, as it's a unix socket", bind_address.address));
+                }
+                println!("Throwscape listening on {}", bind_address.address);
+                listeners.spawn(accept_unix_connections(listener, Arc::clone(&config), Arc::clone(&pool), shutdown.signal()));
+                continue;
+            }
+        };
+        let local_address = listener
+            .local_addr()
+            .map_err(|err| format!("Could not read listener address: {}", err))?;
+
+        // the QUIC listener shares the address of the TCP one, only on UDP
+        let quic_certificate_store = certificate_store
+            .as_ref()
+            .filter(|_| bind_address.is_tls && config.is_http3_enabled);
+        if let Some(certificate_store) = quic_certificate_store {
+            let socket = listener::bind_udp(local_address, is_dual_stack)
+                .map_err(|err| format!("Could not bind QUIC listener to {}: {}", local_address, err))?;
+            let endpoint = http3::create_endpoint(certificate_store, socket, &config)?;
+            tokio::spawn(http3::serve(endpoint, Arc::clone(&config), Arc::clone(&pool), shutdown.signal()));
+        }
+
+        if quic_certificate_store.is_some() {
+            println!("Throwscape listening on {} (TLS, HTTP/3)", local_address);
+        } else if bind_address.is_tls {
+            println!("Throwscape listening on {} (TLS)", local_address);
+        } else {
+            println!("Throwscape listening on {}", local_address);
+        }
+
+        let tls_acceptor = tls_acceptor.clone().filter(|_| bind_address.is_tls);
+        let alt_svc_port = quic_certificate_store.map(|_| local_address.port());
+        listeners.spawn(accept_connections(
+            listener,
+            tls_acceptor,
+            alt_svc_port,
+            Arc::clone(&config),
+            Arc::clone(&pool),
+            shutdown.signal(),
+        ));
+    }
+
+    // listeners only stop on their own if the runtime goes away
+    tokio::select! {
+        result = shutdown::wait_for_termination() => {
+            result.map_err(|err| format!("Could not listen for signals: {}", err))?;
+        }
+        _ = async { while listeners.join_next().await.is_some() {} } => {}
+    }
+
+    // new connections are refused right away, running requests get until the drain timeout to finish
+    println!("Throwscape shutting down, waiting up to {}s for running requests", config.drain_timeout.as_secs());
+    if let Some(reload_task) = reload_task {
+        reload_task.abort();
+    }
+    let is_drained = shutdown.trigger_and_drain(config.drain_timeout).await;
+    for path in unix_socket_paths {
+        let _ = fs::remove_file(path);
+    }
+
+    if !is_drained {
+        println!("Throwscape closing the connections that are still open");
+        // queued jobs are dropped, running ones are abandoned along with their connections
+        pool.shutdown_now();
+        return Ok(());
+    }
+    // every connection is gone, so waiting for the workers can't block on anything but their current job
+    drop(listeners);
+    if let Ok(mut pool) = Arc::try_unwrap(pool) {
+        let _ = tokio::task::spawn_blocking(move || pool.shutdown()).await;
+    }
+    Ok(())
+}
+
+async fn accept_connections(
+    listener: TcpListener,
+    tls_acceptor: Option<TlsAcceptor>,
+    alt_svc_port: Option<u16>,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+    mut shutdown: ShutdownSignal,
+) {
+    loop {
+        let stream = tokio::select! {
+            accepted = async {
+                wait_for_capacity(&config, &pool).await;
+                listener.accept().await
+            } => match accepted {
+                Ok((stream, _)) => stream,
+                Err(_) => continue,
+            },
+            _ = shutdown.triggered() => return,
         };
 
         let config = Arc::clone(&config);
+        let pool = Arc::clone(&pool);
+        let tls_acceptor = tls_acceptor.clone();
+        let shutdown = shutdown.clone();
 
-        pool.handle_job(Box::new(move || {
-            let request = match HttpRequest::new(&mut stream, &config) {
-                Ok(request) => request,
-                Err(err) => {
-                    if config.is_in_debug_mode {
-                        eprintln!("Request Error: {:?}", err);
+        tokio::spawn(async move {
+            match tls_acceptor {
+                Some(tls_acceptor) => match time::timeout(config.timeout, tls_acceptor.accept(stream)).await {
+                    Ok(Ok(tls_stream)) => {
+                        if tls_stream.get_ref().1.alpn_protocol() == Some(b"h2") {
+                            http2::serve_connection(tls_stream, alt_svc_port, config, pool, shutdown).await;
+                        } else {
+                            handle_connection(tls_stream, alt_svc_port, config, pool, false, shutdown).await;
+                        }
                     }
-                    return;
-                }
-            };
+                    Ok(Err(err)) => {
+                        if config.is_in_debug_mode {
+                            eprintln!("TLS Error: {}", err);
+                        }
+                    }
+                    Err(_) => {
+                        if config.is_in_debug_mode {
+                            eprintln!("TLS Error: handshake timed out");
+                        }
+                    }
+                },
+                None => handle_connection(stream, None, config, pool, true, shutdown).await,
+            }
+        });
+    }
+}
 
-            if config.is_in_debug_mode {
-                println!("{}", request.to_string());
+async fn accept_unix_connections(
+    listener: UnixListener,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+    mut shutdown: ShutdownSignal,
+) {
+    loop {
+        let stream = tokio::select! {
+            accepted = async {
+                wait_for_capacity(&config, &pool).await;
+                listener.accept().await
+            } => match accepted {
+                Ok((stream, _)) => stream,
+                Err(_) => continue,
+            },
+            _ = shutdown.triggered() => return,
+        };
+
+        let config = Arc::clone(&config);
+        let pool = Arc::clone(&pool);
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { handle_connection(stream, None, config, pool, true, shutdown).await });
+    }
+}
+
+// cleartext connections can switch to HTTP/2 by prior knowledge or an upgrade, TLS ones negotiate it with ALPN
+// alt_svc_port is the port of the QUIC listener next to this one, if there is any
+async fn handle_connection<S>(
+    mut stream: S,
+    alt_svc_port: Option<u16>,
+    config: Arc<Config>,
+    pool: Arc<ThreadPool>,
+    is_cleartext: bool,
+    mut shutdown: ShutdownSignal,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // cleartext connections may start with HTTP/2 right away, the bytes read to find out are replayed either way
+    let read_bytes = if is_cleartext {
+        match time::timeout(config.timeout, http2::read_preface(&mut stream)).await {
+            Ok(Ok(read_bytes)) => read_bytes,
+            _ => return,
+        }
+    } else {
+        vec![]
+    };
+    let is_http2 = http2::is_connection_preface(&read_bytes);
+    let stream = PrefixedStream::new(read_bytes, stream);
+    if is_http2 {
+        return http2::serve_connection(stream, alt_svc_port, config, pool, shutdown).await;
+    }
+
+    let (reader, mut writer) = io::split(stream);
+    let mut buffered_reader = BufReader::new(reader);
+    let mut is_first_request = true;
+
+    loop {
+        if !is_first_request && !wait_for_next_request(&mut buffered_reader, config.keep_alive_timeout, &mut shutdown).await {
+            return;
+        }
+        is_first_request = false;
+
+        let mut request = match HttpRequest::new(&mut buffered_reader, &config).await {
+            Ok(request) => request,
+            Err(err) => return reject_request(err, &mut buffered_reader, &mut writer, &config).await,
+        };
+
+        if config.is_in_debug_mode {
+            println!("{}", request);
+        }
+
+        // no file is answered based on the body, but it has to be off the connection before the next request
+        let mut body = BodyReader::new(&mut buffered_reader, request.body_framing, config.max_body_size);
+        let discarded = match time::timeout(config.timeout, body.discard()).await {
+            Ok(discarded) => discarded,
+            Err(_) => Err(HttpParsingError::Timeout),
+        };
+        if let Err(err) = discarded {
+            return reject_request(err, &mut buffered_reader, &mut writer, &config).await;
+        }
+
+        // requests that were already underway are still answered, but with "Connection: close"
+        if shutdown.is_triggered() {
+            request.meta_data.keep_alive = false;
+        }
+
+        if let Some(upgrade_frame) = http2::prepare_upgrade(&request).filter(|_| is_cleartext) {
+            if writer.write_all(http2::SWITCHING_PROTOCOLS_RESPONSE).await.is_err() {
+                return;
             }
+            let stream = http2::reunite(buffered_reader, writer);
+            return http2::serve_upgraded_connection(stream, upgrade_frame, alt_svc_port, config, pool, shutdown).await;
+        }
 
-            let response = HttpResponse::new(request, &config);
-            match response.send(&mut stream) {
-                Ok(_) => {}
-                Err(err) => {
-                    if config.is_in_debug_mode {
-                        eprintln!("Response Error: {}", err);
-                    }
-                }
+        let mut response = generate_response(request, &config, &pool).await;
+        if let Some(alt_svc_port) = alt_svc_port {
+            response.add_header(http3::generate_alt_svc_header(alt_svc_port));
+        }
+
+        if let Err(err) = response.send(&mut writer).await {
+            if config.is_in_debug_mode {
+                eprintln!("Response Error: {}", err);
             }
-        }));
+            return;
+        }
+
+        if !response.keeps_connection_alive() {
+            return;
+        }
+    }
+}
+
+// it's unclear where the next request would start, so the connection is closed either way
+async fn reject_request<R, W>(err: HttpParsingError, reader: &mut R, writer: &mut W, config: &Config)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if config.is_in_debug_mode {
+        eprintln!("Request Error: {:?}", err);
+    }
+    if let Some(mut response) = HttpResponse::new_parsing_error(&err) {
+        if response.send(writer).await.is_ok() {
+            close_after_error(reader, writer).await;
+        }
+    }
+}
+
+// the client may still be sending the rest of a broken request, closing right away would reset the connection
+// and can discard the error response before the client got to read it
+async fn close_after_error<R, W>(reader: &mut R, writer: &mut W)
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    if writer.shutdown().await.is_err() {
+        return;
+    }
+    let _ = time::timeout(LINGER_TIMEOUT, io::copy(&mut reader.take(LINGER_LIMIT), &mut io::sink())).await;
+}
+
+// idle connections are only kept open for the keep alive timeout, a started request gets the regular timeout
+async fn wait_for_next_request<R>(buffered_reader: &mut R, keep_alive_timeout: Duration, shutdown: &mut ShutdownSignal) -> bool
+where
+    R: AsyncBufRead + Unpin,
+{
+    if shutdown.is_triggered() {
+        return false;
+    }
+
+    tokio::select! {
+        filled = time::timeout(keep_alive_timeout, buffered_reader.fill_buf()) => match filled {
+            Ok(Ok(buffer)) => !buffer.is_empty(),
+            _ => false,
+        },
+        _ = shutdown.triggered() => false,
+    }
+}
+
+// with the "block" overload policy, new connections are left waiting in the backlog while the queue of the pool is full
+pub async fn wait_for_capacity(config: &Config, pool: &ThreadPool) {
+    if config.overload_policy == OverloadPolicy::Block {
+        pool.wait_for_capacity().await;
+    }
+}
+
+// answers the request on the pool, with the "reject" overload policy a full queue is answered with a 503 right away,
+// a request whose job panicked gets a 500
+pub async fn generate_response(request: HttpRequest, config: &Arc<Config>, pool: &ThreadPool) -> HttpResponse {
+    let meta_data = request.meta_data.clone();
+    let response_config = Arc::clone(config);
+    let create_response = move || HttpResponse::new(request, &response_config);
+
+    let response = match config.overload_policy {
+        OverloadPolicy::Block => pool.handle_blocking(create_response).await,
+        OverloadPolicy::Reject => match pool.try_handle_blocking(create_response).await {
+            Ok(response) => response,
+            Err(QueueFull) => return HttpResponse::new_overloaded(meta_data),
+        },
+    };
+    response.unwrap_or_else(|| HttpResponse::new_internal_error(meta_data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc};
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use crate::configuration::Config;
+    use crate::http::HttpRequest;
+    use crate::scheduler::ThreadPool;
+
+    #[tokio::test]
+    async fn rejected_head_request_has_no_content() {
+        let args = [
+            String::from("throwscape"),
+            String::from("--overload-policy"), String::from("reject"),
+        ];
+        let config = Arc::new(Config::new(&args).unwrap());
+        let pool = ThreadPool::new(1, 1);
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+
+        // occupies the only worker, then the only place in the queue
+        let running = pool.handle_blocking(move || release_rx.recv().unwrap());
+        tokio::pin!(running);
+        assert!(time::timeout(Duration::from_millis(50), &mut running).await.is_err());
+        let queued = pool.handle_blocking(|| ());
+        tokio::pin!(queued);
+        assert!(time::timeout(Duration::from_millis(50), &mut queued).await.is_err());
+
+        let request = HttpRequest::new(&mut "HEAD / HTTP/1.1\r\n\r\n".as_bytes(), &config).await.unwrap();
+        let mut response = super::generate_response(request, &config, &pool).await;
+        let mut sent = vec![];
+        response.send(&mut sent).await.unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(sent.contains("Content-Length: 19\r\n"));
+        assert!(sent.ends_with("\r\n\r\n"));
+
+        release_tx.send(()).unwrap();
+        assert_eq!(running.await, Some(()));
+        assert_eq!(queued.await, Some(()));
     }
 }
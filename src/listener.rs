@@ -0,0 +1,167 @@
+use std::fs::{self, Permissions};
+use std::io;
+use std::net::{self, SocketAddr};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::{self as unix_net, UnixStream};
+use std::path::Path;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, UnixListener};
+
+const BACKLOG: i32 = 1024;
+
+pub enum Listener {
+    Tcp { listener: TcpListener, is_dual_stack: bool },
+    Unix(UnixListener),
+}
+
+// sockets are created by hand, as whether "[::]" accepts IPv4 as well otherwise depends on the system (ex. bindv6only on linux)
+pub fn bind_tcp(address: SocketAddr, is_dual_stack: bool) -> io::Result<TcpListener> {
+    let socket = create_socket(address, is_dual_stack, Type::STREAM, Protocol::TCP)?;
+    socket.listen(BACKLOG)?;
+    TcpListener::from_std(net::TcpListener::from(socket))
+}
+
+pub fn bind_udp(address: SocketAddr, is_dual_stack: bool) -> io::Result<net::UdpSocket> {
+    let socket = create_socket(address, is_dual_stack, Type::DGRAM, Protocol::UDP)?;
+    Ok(net::UdpSocket::from(socket))
+}
+
+pub fn bind_unix(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+// sockets aren't removed when a process gets killed, one nobody accepts connections on is left over from a previous run
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    if !metadata.file_type().is_socket() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "file exists and is not a socket"));
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(io::ErrorKind::AddrInUse, "socket is used by another process")),
+        Err(_) => fs::remove_file(path),
+    }
+}
+
+// takes ownership of the descriptor, the config makes sure every one is only inherited once
+pub fn inherit(fd: RawFd) -> io::Result<Listener> {
+    let socket = unsafe { Socket::from_raw_fd(fd) };
+    let is_listener = socket.r#type().map(|socket_type| socket_type == Type::STREAM).unwrap_or(false)
+        && socket.is_listener().unwrap_or(false);
+    if !is_listener {
+        // left open, as a wrong number could point anywhere (ex. stdout)
+        let _ = socket.into_raw_fd();
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a listening stream socket"));
+    }
+    // inherited descriptors usually come without it, they'd leak into every process started later on otherwise
+    socket.set_cloexec(true)?;
+    socket.set_nonblocking(true)?;
+
+    let local_address = socket.local_addr()?;
+    match local_address.as_socket() {
+        Some(address) => {
+            let is_dual_stack = address.is_ipv6() && !socket.only_v6()?;
+            let listener = TcpListener::from_std(net::TcpListener::from(socket))?;
+            Ok(Listener::Tcp { listener, is_dual_stack })
+        }
+        None if local_address.is_unix() => Ok(Listener::Unix(UnixListener::from_std(unix_net::UnixListener::from(socket))?)),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "neither an IP nor a unix socket")),
+    }
+}
+
+fn create_socket(address: SocketAddr, is_dual_stack: bool, socket_type: Type, protocol: Protocol) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(address), socket_type, Some(protocol))?;
+    if address.is_ipv6() {
+        socket.set_only_v6(!is_dual_stack)?;
+    }
+    // allows restarting right away, while connections of the previous process are still in TIME_WAIT
+    if socket_type == Type::STREAM {
+        socket.set_reuse_address(true)?;
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpStream};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::{AsRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+    use std::fs;
+
+    use crate::test_util::TempDirectory;
+
+    #[tokio::test]
+    async fn unix_socket_replaces_stale_one() {
+        let directory = TempDirectory::new("throwscape_listener_test");
+        let path = directory.join("listener.sock");
+
+        let listener = super::bind_unix(&path, 0o600).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(super::bind_unix(&path, 0o600).is_err());
+
+        // dropping the listener leaves the socket file behind, just like a killed process
+        drop(listener);
+        let listener = super::bind_unix(&path, 0o660).unwrap();
+        let client = std::thread::spawn({
+            let path = path.clone();
+            move || UnixStream::connect(path).is_ok()
+        });
+        listener.accept().await.unwrap();
+        assert!(client.join().unwrap());
+
+        // regular files are never removed
+        drop(listener);
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "").unwrap();
+        assert!(super::bind_unix(&path, 0o660).is_err());
+    }
+
+    #[tokio::test]
+    async fn inherits_listening_sockets_only() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let fd = listener.into_raw_fd();
+        // std sets close-on-exec by itself, a socket passed down by another process doesn't have it
+        unsafe { libc::fcntl(fd, libc::F_SETFD, 0) };
+        match super::inherit(fd).unwrap() {
+            super::Listener::Tcp { listener, is_dual_stack } => {
+                assert_eq!(listener.local_addr().unwrap(), address);
+                assert!(!is_dual_stack);
+                let fd_flags = unsafe { libc::fcntl(listener.as_raw_fd(), libc::F_GETFD) };
+                assert_ne!(fd_flags & libc::FD_CLOEXEC, 0);
+            }
+            super::Listener::Unix(_) => panic!("inherited a unix socket"),
+        }
+
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert!(super::inherit(socket.into_raw_fd()).is_err());
+    }
+
+    #[tokio::test]
+    async fn dual_stack_listener_accepts_ipv4() {
+        let listener = match super::bind_tcp("[::]:0".parse().unwrap(), true) {
+            Ok(listener) => listener,
+            // IPv6 can be disabled entirely, ex. in some containers
+            Err(_) => return,
+        };
+        let port = listener.local_addr().unwrap().port();
+
+        let client = std::thread::spawn(move || TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).is_ok());
+        let (_, peer_address) = listener.accept().await.unwrap();
+
+        assert!(client.join().unwrap());
+        assert!(peer_address.ip().to_canonical().is_ipv4());
+    }
+}
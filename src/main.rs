@@ -1,24 +1,36 @@
-use std::{net::{TcpListener, SocketAddr}, process, env, sync::Arc};
-use throwscape::configuration::Config;
+use std::{process, env, sync::Arc};
+use throwscape::configuration::{self, Config, ConfigError};
 
+// the runtime is built by hand, so the environment is only changed while there is just this one thread
 fn main() {
     // retrieve configuration
     let args: Vec<String> = env::args().collect();
     let config = match Config::new(&args) {
         Ok(conf) => conf,
+        Err(ConfigError::HelpRequested) => {
+            print!("{}", configuration::generate_help());
+            return;
+        }
+        Err(ConfigError::VersionRequested) => {
+            println!("{}", configuration::generate_version());
+            return;
+        }
         Err(error_msg) => {
             eprintln!("ERROR: {}", error_msg);
             process::exit(1);
         }
-    }; 
+    };
+    configuration::unset_systemd_variables();
 
-    // open tcp port
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
-
-    if let Ok(listener) = TcpListener::bind(addr) {
-        throwscape::run(Arc::new(config), listener);
-    } else {
-        eprintln!("Could not bind to port");
+    let runtime = match tokio::runtime::Builder::new_multi_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(error_msg) => {
+            eprintln!("ERROR: {}", error_msg);
+            process::exit(1);
+        }
+    };
+    if let Err(error_msg) = runtime.block_on(throwscape::run(Arc::new(config))) {
+        eprintln!("ERROR: {}", error_msg);
         process::exit(1);
     }
 }
@@ -1,4 +1,3 @@
-pub use thread_pool::ThreadPool;
+pub use thread_pool::{Job, QueueFull, ThreadPool};
 
 mod thread_pool;
-mod scheduler;
@@ -1,60 +1,509 @@
-use std::thread;
-use std::sync::mpsc;
+use std::any::Any;
+use std::io;
+use std::iter;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{self, AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
 
-use super::scheduler::{Job, Scheduler};
+use crossbeam_deque::{self as deque, Injector, Steal, Stealer};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
 
+pub type Job = Box<dyn FnMut() + Send>;
+
+// every worker has a deque of its own, fed in batches from the shared injector,
+// idle workers steal from the others before going to sleep,
+// dropping the pool shuts it down and joins the workers, unless shutdown_now already gave up on them
 pub struct ThreadPool {
-    _workers: Vec<Worker>,
-    scheduler: Scheduler,
+    shared: Arc<Shared>,
+    // one permit per free place in the queue, async callers wait on these before handing in a job
+    queue_slots: Arc<Semaphore>,
+}
+
+// returned instead of queueing a job once the queue is full
+#[derive(Debug, PartialEq)]
+pub struct QueueFull;
+
+struct Shared {
+    injector: Injector<Job>,
+    stealers: Vec<Stealer<Job>>,
+    // only guards going to sleep and waking up, handing in a job doesn't touch it
+    sleep_lock: Mutex<()>,
+    job_available: Condvar,
+    // a worker counts itself in before its last look for a job, so a job handed in meanwhile either gets found
+    // or wakes it up
+    sleeping_workers: AtomicUsize,
+    // only changed while holding the sleep lock, so a worker can't miss it between looking and going to sleep
+    is_shut_down: AtomicBool,
+    // jobs being handed in right now, the workers don't stop at a shutdown before these are in the injector
+    submitting_jobs: AtomicUsize,
+    // set by shutdown_now, workers stop instead of running what's left
+    is_stopped: AtomicBool,
+    // a dying worker puts its replacement in here
+    workers: Mutex<Vec<Worker>>,
 }
 
 struct Worker {
     pub _id: u32,
+    thread: Option<JoinHandle<()>>,
 }
 
 impl ThreadPool {
-    pub fn new(threads: u32) -> ThreadPool {
+    pub fn new(threads: u32, queue_size: usize) -> ThreadPool {
         if threads == 0 {
             panic!("Invalid thread count provided");
         }
+        if queue_size == 0 {
+            panic!("Invalid queue size provided");
+        }
 
-        let (waiting_threads_tx, waiting_threads_rx) = mpsc::channel::<u32>();
+        let deques: Vec<deque::Worker<Job>> = (0..threads).map(|_| deque::Worker::new_fifo()).collect();
+        let shared = Arc::new(Shared {
+            injector: Injector::new(),
+            stealers: deques.iter().map(deque::Worker::stealer).collect(),
+            sleep_lock: Mutex::new(()),
+            job_available: Condvar::new(),
+            sleeping_workers: AtomicUsize::new(0),
+            is_shut_down: AtomicBool::new(false),
+            submitting_jobs: AtomicUsize::new(0),
+            is_stopped: AtomicBool::new(false),
+            workers: Mutex::new(Vec::with_capacity(threads as usize)),
+        });
 
-        let mut workers = Vec::with_capacity(threads as usize);
-        let mut outgoing_job_senders = Vec::with_capacity(threads as usize);
+        for (id, local_jobs) in (0..threads).zip(deques) {
+            let thread = spawn_worker(id, local_jobs, Arc::clone(&shared)).expect("Could not spawn worker thread");
+            shared.workers.lock().unwrap().push(Worker {
+                _id: id,
+                thread: Some(thread),
+            });
+        }
 
-        for id in 0..threads {
-            let current_waiting_sender = waiting_threads_tx.clone();
-            let (current_job_tx, current_job_rx) = mpsc::channel::<Job>();
-            outgoing_job_senders.push(current_job_tx);
+        ThreadPool {
+            shared,
+            queue_slots: Arc::new(Semaphore::new(queue_size)),
+        }
+    }
 
-            let current_worker = Worker {
-                _id: id,
-            };
+    // jobs handed in directly don't count against the queue size, async code should use handle_blocking instead
+    pub fn handle_job(&self, job: Job) {
+        self.shared.submitting_jobs.fetch_add(1, Ordering::SeqCst);
+        // jobs assigned after shutting down are dropped without running
+        if self.shared.is_shut_down.load(Ordering::SeqCst) {
+            self.shared.submitting_jobs.fetch_sub(1, Ordering::SeqCst);
+            return;
+        }
+        self.shared.injector.push(job);
+        self.shared.submitting_jobs.fetch_sub(1, Ordering::SeqCst);
 
-            thread::spawn(move || {
-                loop {
-                    current_waiting_sender.send(id).unwrap();
+        // pairs with the one in wait_for_job, either the job is found there or the sleeping worker is seen here
+        atomic::fence(Ordering::SeqCst);
+        if self.shared.sleeping_workers.load(Ordering::SeqCst) > 0 {
+            // the worker only lets go of the lock once it is waiting, so the notification can't get lost
+            let _sleep_guard = self.shared.sleep_lock.lock();
+            self.shared.job_available.notify_one();
+        }
+    }
 
-                    let mut job = current_job_rx.recv().unwrap();
+    // finishes every job handed in so far, then stops the worker threads
+    pub fn shutdown(&mut self) {
+        self.signal_shutdown();
 
-                    job();
+        let worker_count = self.shared.workers.lock().map(|workers| workers.len()).unwrap_or(0);
+        for id in 0..worker_count {
+            // a worker dying in the meantime leaves its replacement behind, which has to be joined as well
+            while let Some(thread) = self.take_worker_thread(id) {
+                // a job holding on to the pool can end up dropping it on a worker, which can't join itself
+                if thread.thread().id() != thread::current().id() {
+                    let _ = thread.join();
                 }
-            });
+            }
+        }
+    }
+
+    // gives up on the jobs still queued and returns without waiting for the workers, their callers get None,
+    // jobs already running are left to finish in the background
+    pub fn shutdown_now(&self) {
+        self.shared.is_stopped.store(true, Ordering::SeqCst);
+        self.signal_shutdown();
 
-            workers.push(current_worker);
+        // local deques can only be emptied through their stealers, a worker taking a batch in the meantime
+        // drops it once it sees the pool is stopped
+        while !self.shared.injector.is_empty() {
+            drop(self.shared.injector.steal());
+        }
+        for stealer in &self.shared.stealers {
+            while !stealer.is_empty() {
+                drop(stealer.steal());
+            }
         }
-        
+    }
+
+    fn signal_shutdown(&self) {
+        let sleep_guard = self.shared.sleep_lock.lock();
+        self.shared.is_shut_down.store(true, Ordering::SeqCst);
+        drop(sleep_guard);
+        self.shared.job_available.notify_all();
+    }
+
+    fn take_worker_thread(&self, id: usize) -> Option<JoinHandle<()>> {
+        self.shared.workers.lock().ok()?.get_mut(id)?.thread.take()
+    }
+
+    // resolves as soon as there is room in the queue, without reserving it
+    pub async fn wait_for_capacity(&self) {
+        let _ = self.queue_slots.acquire().await;
+    }
 
-        ThreadPool { 
-            _workers: workers,
-            scheduler: Scheduler::new(waiting_threads_rx, outgoing_job_senders), 
+    // runs blocking work (ex. file system access) on the pool without stalling the async runtime,
+    // waits for room in the queue first
+    pub async fn handle_blocking<F, T>(&self, task: F) -> Option<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let queue_slot = Arc::clone(&self.queue_slots).acquire_owned().await.ok()?;
+        self.queue_task(task, queue_slot).await
+    }
+
+    // like handle_blocking, but gives up right away if the queue is full
+    pub async fn try_handle_blocking<F, T>(&self, task: F) -> Result<Option<T>, QueueFull>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let queue_slot = Arc::clone(&self.queue_slots).try_acquire_owned().map_err(|_| QueueFull)?;
+        Ok(self.queue_task(task, queue_slot).await)
+    }
+
+    async fn queue_task<F, T>(&self, task: F, queue_slot: OwnedSemaphorePermit) -> Option<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let mut pending_task = Some((task, result_tx, queue_slot));
+
+        self.handle_job(Box::new(move || {
+            if let Some((task, result_tx, queue_slot)) = pending_task.take() {
+                // the job left the queue once a worker runs it
+                drop(queue_slot);
+                let _ = result_tx.send(task());
+            }
+        }));
+
+        // sender is dropped without a result if the job never ran to completion
+        result_rx.await.ok()
+    }
+}
+
+impl Shared {
+    // own jobs first, then a batch from the injector, then whatever another worker has queued up
+    fn find_job(&self, local_jobs: &deque::Worker<Job>) -> Option<Job> {
+        local_jobs.pop().or_else(|| {
+            iter::repeat_with(|| {
+                self.injector
+                    .steal_batch_and_pop(local_jobs)
+                    .or_else(|| self.stealers.iter().map(Stealer::steal).collect())
+            })
+            .find(|steal| !steal.is_retry())
+            .and_then(Steal::success)
+        })
+    }
+
+    // waits until there might be something to do, returns None once the pool is shut down and no job is left
+    fn wait_for_job(&self, local_jobs: &deque::Worker<Job>) -> Option<Job> {
+        loop {
+            let sleep_guard = self.sleep_lock.lock().ok()?;
+            self.sleeping_workers.fetch_add(1, Ordering::SeqCst);
+            atomic::fence(Ordering::SeqCst);
+            // read before looking, so a job handed in before the shutdown is found below
+            let is_shut_down = self.is_shut_down.load(Ordering::SeqCst);
+            let is_job_submitted = self.submitting_jobs.load(Ordering::SeqCst) > 0;
+
+            if let Some(job) = self.find_job(local_jobs) {
+                self.sleeping_workers.fetch_sub(1, Ordering::SeqCst);
+                return Some(job);
+            }
+            if is_shut_down {
+                self.sleeping_workers.fetch_sub(1, Ordering::SeqCst);
+                if !is_job_submitted {
+                    return None;
+                }
+                // a job that got past the shutdown check is on its way into the injector
+                drop(sleep_guard);
+                thread::yield_now();
+                continue;
+            }
+
+            let woken_guard = self.job_available.wait(sleep_guard);
+            self.sleeping_workers.fetch_sub(1, Ordering::SeqCst);
+            if woken_guard.is_err() {
+                return None;
+            }
         }
-        
     }
+}
 
-    pub fn handle_job(&self, job: Job) {
-        self.scheduler.assign(job);
+fn spawn_worker(id: u32, local_jobs: deque::Worker<Job>, shared: Arc<Shared>) -> io::Result<JoinHandle<()>> {
+    thread::Builder::new().name(format!("throwscape-worker-{}", id)).spawn(move || {
+        let sentinel = WorkerSentinel { id, local_jobs: Some(local_jobs), shared };
+        let (local_jobs, shared) = match &sentinel.local_jobs {
+            Some(local_jobs) => (local_jobs, &sentinel.shared),
+            None => return,
+        };
+
+        loop {
+            // only takes the lock once there is nothing to find right away
+            let job = match shared.find_job(local_jobs) {
+                Some(job) => job,
+                None => match shared.wait_for_job(local_jobs) {
+                    Some(job) => job,
+                    None => break,
+                },
+            };
+            // the job and the rest of the local deque are dropped with the thread
+            if shared.is_stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            // the rest of a batch taken from the injector can be stolen by a worker that is asleep
+            if !local_jobs.is_empty() && shared.sleeping_workers.load(Ordering::SeqCst) > 0 {
+                shared.job_available.notify_one();
+            }
+
+            // a panicking job only loses its own result, the worker carries on with the next one
+            if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                eprintln!("Job Error: worker {} panicked: {}", id, describe_panic(panic.as_ref()));
+            }
+        }
+    })
+}
+
+// lives on the stack of a worker thread and starts a replacement if a panic still gets through,
+// ex. from dropping the payload of a caught one
+struct WorkerSentinel {
+    id: u32,
+    local_jobs: Option<deque::Worker<Job>>,
+    shared: Arc<Shared>,
+}
+
+impl Drop for WorkerSentinel {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            return;
+        }
+        // the deque moves on to the replacement, so jobs queued on it aren't lost
+        let local_jobs = match self.local_jobs.take() {
+            Some(local_jobs) => local_jobs,
+            None => return,
+        };
+
+        match spawn_worker(self.id, local_jobs, Arc::clone(&self.shared)) {
+            Ok(thread) => {
+                eprintln!("Job Error: worker {} died and was replaced", self.id);
+                if let Some(worker) = self.shared.workers.lock().ok().as_mut().and_then(|workers| workers.get_mut(self.id as usize)) {
+                    worker.thread = Some(thread);
+                }
+            }
+            Err(err) => eprintln!("Job Error: worker {} died and could not be replaced: {}", self.id, err),
+        }
+    }
+}
+
+fn describe_panic(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic.downcast_ref::<String>().map(String::as_str).unwrap_or("unknown cause"),
+    }
+}
+
+// waits for the queued jobs just like shutdown, after shutdown_now the workers are left to stop on their own,
+// so a pool dropped past the drain timeout doesn't block on jobs that might never finish
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        if self.shared.is_stopped.load(Ordering::SeqCst) {
+            self.signal_shutdown();
+        } else {
+            self.shutdown();
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::mpsc::{self, TryRecvError};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{QueueFull, ThreadPool};
+
+    #[test]
+    fn shutdown_finishes_queued_jobs() {
+        let mut pool = ThreadPool::new(2, 8);
+        let finished_jobs = Arc::new(AtomicU32::new(0));
+
+        for _ in 0..8 {
+            let finished_jobs = Arc::clone(&finished_jobs);
+            pool.handle_job(Box::new(move || {
+                thread::sleep(Duration::from_millis(10));
+                finished_jobs.fetch_add(1, Ordering::SeqCst);
+            }));
+        }
+        pool.shutdown();
+
+        assert_eq!(finished_jobs.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn jobs_handed_in_concurrently_all_run() {
+        // workers keep going to sleep in between, a lost wakeup leaves a job behind until the pool is shut down
+        let pool = Arc::new(ThreadPool::new(4, 8));
+        let (finished_tx, finished_rx) = mpsc::channel();
+
+        let submitters: Vec<_> = (0..8)
+            .map(|_| {
+                let pool = Arc::clone(&pool);
+                let finished_tx = finished_tx.clone();
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        let finished_tx = finished_tx.clone();
+                        pool.handle_job(Box::new(move || {
+                            let _ = finished_tx.send(());
+                        }));
+                    }
+                })
+            })
+            .collect();
+        for submitter in submitters {
+            submitter.join().unwrap();
+        }
+
+        for _ in 0..8 * 500 {
+            finished_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+
+    #[test]
+    fn drop_finishes_queued_jobs() {
+        let pool = ThreadPool::new(2, 8);
+        let finished_jobs = Arc::new(AtomicU32::new(0));
+
+        for _ in 0..8 {
+            let finished_jobs = Arc::clone(&finished_jobs);
+            pool.handle_job(Box::new(move || {
+                thread::sleep(Duration::from_millis(10));
+                finished_jobs.fetch_add(1, Ordering::SeqCst);
+            }));
+        }
+        drop(pool);
+
+        assert_eq!(finished_jobs.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn shutdown_now_drops_queued_jobs() {
+        let pool = ThreadPool::new(1, 8);
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        pool.handle_job(Box::new(move || {
+            let _ = started_tx.send(());
+            let _ = release_rx.recv();
+        }));
+        started_rx.recv().unwrap();
+
+        let (ran_tx, ran_rx) = mpsc::channel();
+        for _ in 0..4 {
+            let ran_tx = ran_tx.clone();
+            pool.handle_job(Box::new(move || {
+                let _ = ran_tx.send(());
+            }));
+        }
+        drop(ran_tx);
+
+        // the queued jobs are gone right away, while the running one still blocks the only worker
+        pool.shutdown_now();
+        assert_eq!(ran_rx.try_recv(), Err(TryRecvError::Disconnected));
+
+        // dropping the pool doesn't wait for it either, the job only finishes once released afterwards
+        drop(pool);
+        release_tx.send(()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn jobs_after_shutdown_are_dropped() {
+        let mut pool = ThreadPool::new(1, 1);
+        pool.shutdown();
+
+        assert_eq!(pool.handle_blocking(|| 1).await, None);
+    }
+
+    #[tokio::test]
+    async fn full_queue_rejects_or_waits() {
+        let pool = ThreadPool::new(1, 1);
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+
+        // occupies the only worker, then the only place in the queue
+        let running = pool.handle_blocking(move || release_rx.recv().unwrap());
+        tokio::pin!(running);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut running).await.is_err());
+        let queued = pool.handle_blocking(|| 2);
+        tokio::pin!(queued);
+        assert!(tokio::time::timeout(Duration::from_millis(50), &mut queued).await.is_err());
+
+        assert_eq!(pool.try_handle_blocking(|| 3).await, Err(QueueFull));
+        assert!(tokio::time::timeout(Duration::from_millis(50), pool.wait_for_capacity()).await.is_err());
+
+        release_tx.send(()).unwrap();
+        assert_eq!(running.await, Some(()));
+        assert_eq!(queued.await, Some(2));
+        assert_eq!(pool.try_handle_blocking(|| 3).await, Ok(Some(3)));
+    }
+
+    #[tokio::test]
+    async fn panicking_job_keeps_the_worker() {
+        let pool = ThreadPool::new(1, 4);
+
+        assert_eq!(pool.handle_blocking(|| -> u32 { panic!("job failed") }).await, None);
+        assert_eq!(pool.handle_blocking(|| 1).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn dead_worker_is_replaced() {
+        struct PanicOnDrop;
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("payload dropped");
+            }
+        }
+
+        // the caught panic kills the worker once its payload is dropped
+        let mut pool = ThreadPool::new(1, 4);
+        assert_eq!(pool.handle_blocking(|| -> u32 { std::panic::panic_any(PanicOnDrop) }).await, None);
+
+        assert_eq!(pool.handle_blocking(|| 1).await, Some(1));
+        pool.shutdown();
+    }
+
+    #[test]
+    fn idle_workers_steal_queued_jobs() {
+        // every job waits for all others, so it only finishes if no worker sits on a batch of them
+        let mut pool = ThreadPool::new(4, 8);
+        let barrier = Arc::new(std::sync::Barrier::new(4));
+        let (finished_tx, finished_rx) = std::sync::mpsc::channel();
+
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            let finished_tx = finished_tx.clone();
+            pool.handle_job(Box::new(move || {
+                barrier.wait();
+                let _ = finished_tx.send(());
+            }));
+        }
+
+        for _ in 0..4 {
+            assert!(finished_rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        }
+        pool.shutdown();
+    }
+}
@@ -0,0 +1,110 @@
+use std::future;
+use std::time::Duration;
+
+use tokio::signal::unix::{self, SignalKind};
+use tokio::sync::{mpsc, watch};
+use tokio::time;
+
+// handed to every listener and connection, shutting down only completes once all of them have been dropped
+#[derive(Clone)]
+pub struct ShutdownSignal {
+    receiver: watch::Receiver<bool>,
+    _drain_guard: mpsc::Sender<()>,
+}
+
+impl ShutdownSignal {
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    // resolves once the server starts shutting down
+    pub async fn triggered(&mut self) {
+        // without a sender it can't be triggered anymore, ex. when the shutdown was dropped in tests
+        if self.receiver.wait_for(|is_triggered| *is_triggered).await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+}
+
+pub struct Shutdown {
+    sender: watch::Sender<bool>,
+    signal: ShutdownSignal,
+    drain_receiver: mpsc::Receiver<()>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        let (sender, receiver) = watch::channel(false);
+        let (drain_sender, drain_receiver) = mpsc::channel(1);
+
+        Shutdown {
+            sender,
+            signal: ShutdownSignal { receiver, _drain_guard: drain_sender },
+            drain_receiver,
+        }
+    }
+
+    pub fn signal(&self) -> ShutdownSignal {
+        self.signal.clone()
+    }
+
+    // returns whether everything finished before the deadline
+    pub async fn trigger_and_drain(self, drain_timeout: Duration) -> bool {
+        let Shutdown { sender, signal, mut drain_receiver } = self;
+        let _ = sender.send(true);
+        drop(signal);
+
+        // nothing is ever sent, recv only returns once every guard is dropped
+        time::timeout(drain_timeout, drain_receiver.recv()).await.is_ok()
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Shutdown {
+        Shutdown::new()
+    }
+}
+
+// SIGTERM is what container runtimes send, SIGINT covers ctrl-c in a terminal
+pub async fn wait_for_termination() -> std::io::Result<()> {
+    let mut terminate = unix::signal(SignalKind::terminate())?;
+    let mut interrupt = unix::signal(SignalKind::interrupt())?;
+
+    tokio::select! {
+        _ = terminate.recv() => {},
+        _ = interrupt.recv() => {},
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use super::Shutdown;
+
+    #[tokio::test]
+    async fn drains_until_every_signal_is_dropped() {
+        let shutdown = Shutdown::new();
+        let mut signal = shutdown.signal();
+        assert!(!signal.is_triggered());
+
+        let connection = tokio::spawn(async move {
+            signal.triggered().await;
+            time::sleep(Duration::from_millis(50)).await;
+        });
+
+        assert!(shutdown.trigger_and_drain(Duration::from_secs(5)).await);
+        assert!(connection.is_finished());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_deadline() {
+        let shutdown = Shutdown::new();
+        let _stuck_connection = shutdown.signal();
+
+        assert!(!shutdown.trigger_and_drain(Duration::from_millis(50)).await);
+    }
+}
@@ -0,0 +1,35 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::process;
+
+// a directory only this test run uses, removed again when the test ends
+pub struct TempDirectory {
+    path: PathBuf,
+}
+
+impl TempDirectory {
+    pub fn new(name: &str) -> TempDirectory {
+        // RandomState is seeded randomly, which is enough to keep parallel runs apart
+        let suffix = RandomState::new().build_hasher().finish();
+        let path = env::temp_dir().join(format!("{}_{}_{:016x}", name, process::id(), suffix));
+        fs::create_dir_all(&path).unwrap();
+        TempDirectory { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.path.join(path)
+    }
+}
+
+impl Drop for TempDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
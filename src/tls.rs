@@ -0,0 +1,255 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use rustls::crypto::ring::{self, sign};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+use crate::configuration::{Config, TlsCertificate};
+
+// how often the certificate files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+struct LoadedCertificate {
+    paths: TlsCertificate,
+    certified_key: Arc<CertifiedKey>,
+    last_modified: (Option<SystemTime>, Option<SystemTime>),
+}
+
+impl LoadedCertificate {
+    fn load(paths: &TlsCertificate) -> Result<LoadedCertificate, String> {
+        let last_modified = get_last_modified(paths);
+
+        let cert_chain = CertificateDer::pem_file_iter(&paths.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| format!("Failed to read certificate {}: {}", paths.cert_path.display(), err))?;
+        if cert_chain.is_empty() {
+            return Err(format!("No certificate found in {}", paths.cert_path.display()));
+        }
+
+        let private_key = PrivateKeyDer::from_pem_file(&paths.key_path)
+            .map_err(|err| format!("Failed to read key {}: {}", paths.key_path.display(), err))?;
+        let signing_key = sign::any_supported_type(&private_key)
+            .map_err(|err| format!("Unsupported key {}: {}", paths.key_path.display(), err))?;
+
+        // both files parse on their own while being replaced one after the other, but don't belong together yet
+        let certified_key = CertifiedKey::new(cert_chain, signing_key);
+        certified_key.keys_match().map_err(|err| {
+            format!("Key {} doesn't match certificate {}: {}", paths.key_path.display(), paths.cert_path.display(), err)
+        })?;
+
+        Ok(LoadedCertificate {
+            paths: paths.clone(),
+            certified_key: Arc::new(certified_key),
+            last_modified,
+        })
+    }
+
+    fn is_valid_for(&self, server_name: &ServerName) -> bool {
+        let end_entity_cert = match self.certified_key.end_entity_cert() {
+            Ok(end_entity_cert) => end_entity_cert,
+            Err(_) => return false,
+        };
+        match webpki::EndEntityCert::try_from(end_entity_cert) {
+            Ok(end_entity_cert) => end_entity_cert.verify_is_valid_for_subject_name(server_name).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+fn get_last_modified(paths: &TlsCertificate) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &std::path::Path| path.metadata().and_then(|meta_data| meta_data.modified()).ok();
+    (modified(&paths.cert_path), modified(&paths.key_path))
+}
+
+// picks the certificate by SNI, the first configured pair serves as default
+pub struct CertificateStore {
+    certificates: RwLock<Vec<LoadedCertificate>>,
+}
+
+impl CertificateStore {
+    pub fn new(paths: &[TlsCertificate]) -> Result<CertificateStore, String> {
+        let certificates = paths
+            .iter()
+            .map(LoadedCertificate::load)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CertificateStore {
+            certificates: RwLock::new(certificates),
+        })
+    }
+
+    fn select_certificate(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let certificates = self.certificates.read().unwrap();
+
+        let server_name = server_name.and_then(|server_name| ServerName::try_from(server_name).ok());
+        let matching_certificate = server_name.and_then(|server_name| {
+            certificates
+                .iter()
+                .find(|certificate| certificate.is_valid_for(&server_name))
+        });
+
+        matching_certificate
+            .or_else(|| certificates.first())
+            .map(|certificate| Arc::clone(&certificate.certified_key))
+    }
+
+    // certificates that fail to load keep their previous version, so a half written file doesn't cause downtime
+    pub fn reload_changed_certificates(&self, is_in_debug_mode: bool) {
+        let changed_paths: Vec<TlsCertificate> = self
+            .certificates
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|certificate| get_last_modified(&certificate.paths) != certificate.last_modified)
+            .map(|certificate| certificate.paths.clone())
+            .collect();
+
+        for paths in changed_paths {
+            match LoadedCertificate::load(&paths) {
+                Ok(reloaded_certificate) => {
+                    let mut certificates = self.certificates.write().unwrap();
+                    if let Some(certificate) = certificates.iter_mut().find(|certificate| certificate.paths == paths) {
+                        *certificate = reloaded_certificate;
+                    }
+                    if is_in_debug_mode {
+                        println!("Reloaded certificate {}", paths.cert_path.display());
+                    }
+                }
+                Err(err) => {
+                    if is_in_debug_mode {
+                        eprintln!("TLS Error: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CertificateStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let certificates = self.certificates.read().unwrap();
+        f.debug_list()
+            .entries(certificates.iter().map(|certificate| &certificate.paths))
+            .finish()
+    }
+}
+
+impl ResolvesServerCert for CertificateStore {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        self.select_certificate(client_hello.server_name())
+    }
+}
+
+pub fn create_acceptor(config: &Config) -> Result<Option<(TlsAcceptor, Arc<CertificateStore>)>, String> {
+    if config.tls_certificates.is_empty() {
+        return Ok(None);
+    }
+
+    let certificate_store = Arc::new(CertificateStore::new(&config.tls_certificates)?);
+    let server_config = create_server_config(&certificate_store, vec![b"h2".to_vec(), b"http/1.1".to_vec()])?;
+
+    Ok(Some((TlsAcceptor::from(Arc::new(server_config)), certificate_store)))
+}
+
+// all listeners share one certificate store, so reloads apply to TCP and QUIC alike
+pub fn create_server_config(
+    certificate_store: &Arc<CertificateStore>,
+    alpn_protocols: Vec<Vec<u8>>,
+) -> Result<ServerConfig, String> {
+    let mut server_config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|err| format!("Invalid TLS configuration: {}", err))?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::clone(certificate_store) as Arc<dyn ResolvesServerCert>);
+    server_config.alpn_protocols = alpn_protocols;
+
+    Ok(server_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use crate::configuration::TlsCertificate;
+    use crate::test_util::TempDirectory;
+
+    use super::CertificateStore;
+
+    fn write_certificate(directory: &Path, name: &str) -> TlsCertificate {
+        let certified_key = rcgen::generate_simple_self_signed(vec![String::from(name)]).unwrap();
+        let paths = TlsCertificate {
+            cert_path: directory.join(format!("{}.pem", name)),
+            key_path: directory.join(format!("{}.key", name)),
+        };
+        fs::write(&paths.cert_path, certified_key.cert.pem()).unwrap();
+        fs::write(&paths.key_path, certified_key.key_pair.serialize_pem()).unwrap();
+        paths
+    }
+
+    #[test]
+    fn selects_certificate_by_server_name() {
+        let directory = TempDirectory::new("throwscape_tls_sni_test");
+        let first = write_certificate(directory.path(), "first.test");
+        let second = write_certificate(directory.path(), "second.test");
+        let store = CertificateStore::new(&[first, second]).unwrap();
+
+        let default_key = store.select_certificate(None).unwrap();
+        let second_key = store.select_certificate(Some("second.test")).unwrap();
+        let unknown_key = store.select_certificate(Some("unknown.test")).unwrap();
+
+        assert!(!Arc::ptr_eq(&default_key, &second_key));
+        assert!(Arc::ptr_eq(&default_key, &unknown_key));
+    }
+
+    #[test]
+    fn reloads_changed_certificates() {
+        let directory = TempDirectory::new("throwscape_tls_reload_test");
+        let paths = write_certificate(directory.path(), "reload.test");
+        let store = CertificateStore::new(std::slice::from_ref(&paths)).unwrap();
+        let original_key = store.select_certificate(None).unwrap();
+
+        store.reload_changed_certificates(false);
+        assert!(Arc::ptr_eq(&original_key, &store.select_certificate(None).unwrap()));
+
+        write_certificate(directory.path(), "reload.test");
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options().write(true).open(&paths.cert_path).unwrap().set_modified(later).unwrap();
+
+        store.reload_changed_certificates(false);
+        assert!(!Arc::ptr_eq(&original_key, &store.select_certificate(None).unwrap()));
+    }
+
+    #[test]
+    fn keeps_certificate_if_reload_fails() {
+        let directory = TempDirectory::new("throwscape_tls_invalid_test");
+        let paths = write_certificate(directory.path(), "invalid.test");
+        let store = CertificateStore::new(std::slice::from_ref(&paths)).unwrap();
+        let original_key = store.select_certificate(None).unwrap();
+
+        fs::write(&paths.cert_path, "not a certificate").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options().write(true).open(&paths.cert_path).unwrap().set_modified(later).unwrap();
+
+        store.reload_changed_certificates(false);
+        assert!(Arc::ptr_eq(&original_key, &store.select_certificate(None).unwrap()));
+
+        // a new certificate next to the old key, as seen in the middle of replacing both
+        let other = write_certificate(directory.path(), "other.test");
+        fs::copy(&other.cert_path, &paths.cert_path).unwrap();
+        let even_later = later + Duration::from_secs(60);
+        File::options().write(true).open(&paths.cert_path).unwrap().set_modified(even_later).unwrap();
+
+        store.reload_changed_certificates(false);
+        assert!(Arc::ptr_eq(&original_key, &store.select_certificate(None).unwrap()));
+    }
+}